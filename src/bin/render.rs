@@ -0,0 +1,153 @@
+//! Renders a single frame of a scene file to an image without opening a window.
+//!
+//! Build it with `--no-default-features` on machines without SDL2 installed.
+
+use nalgebra_glm::Vec4;
+use rasterizer::color;
+use rasterizer::models::model::Model;
+use rasterizer::rendering::canvas::Canvas;
+use rasterizer::rendering::gltf;
+use rasterizer::rendering::renderer::{RenderMode, Renderer};
+use rasterizer::rendering::scene_file;
+use rasterizer::rendering::viewport::Viewport;
+use std::path::Path;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+usage: render <scene|.gltf|.glb> --output <image.png|image.ppm> [options]
+
+options:
+    --size <width>x<height>        image size in pixels (default 1280x720)
+    --mode <mode>                  wireframe, filled, shaded, phong or
+                                   filled-wireframe (default shaded)
+    --camera <x>,<y>,<z>           camera position (default 0,0,0)
+    --camera-rotation <x>,<y>,<z>  camera rotation in radians (default 0,0,0)";
+
+/// Everything we were asked to do
+struct Options {
+    scene: String,
+    output: String,
+    width: i32,
+    height: i32,
+    mode: RenderMode,
+    camera: Vec4,
+    camera_rotation: Vec4,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut scene = None;
+    let mut output = None;
+    let mut options = Options {
+        scene: String::new(),
+        output: String::new(),
+        width: 1280,
+        height: 720,
+        mode: RenderMode::Shaded,
+        camera: Vec4::new(0.0, 0.0, 0.0, 0.0),
+        camera_rotation: Vec4::new(0.0, 0.0, 0.0, 0.0),
+    };
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            if scene.replace(arg).is_some() {
+                return Err("only one scene can be rendered at a time".to_string());
+            }
+            continue;
+        }
+        let value = args.next().ok_or_else(|| format!("{arg} needs a value"))?;
+        match arg.as_str() {
+            "--output" | "-o" => output = Some(value),
+            "--size" => {
+                let (width, height) = value
+                    .split_once('x')
+                    .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                    .filter(|&(w, h): &(i32, i32)| w > 0 && h > 0)
+                    .ok_or_else(|| format!("invalid size {value}"))?;
+                (options.width, options.height) = (width, height);
+            }
+            "--mode" => options.mode = value.parse()?,
+            "--camera" => options.camera = vector(&value)?,
+            "--camera-rotation" => options.camera_rotation = vector(&value)?,
+            _ => return Err(format!("unknown option {arg}")),
+        }
+    }
+
+    options.scene = scene.ok_or("missing scene")?;
+    options.output = output.ok_or("missing --output")?;
+    Ok(options)
+}
+
+/// Parses `x,y,z`
+fn vector(value: &str) -> Result<Vec4, String> {
+    let parts: Vec<f32> = value
+        .split(',')
+        .map(|part| part.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("invalid vector {value}"))?;
+    match parts[..] {
+        [x, y, z] => Ok(Vec4::new(x, y, z, 0.0)),
+        _ => Err(format!("expected 3 components in {value}")),
+    }
+}
+
+fn render(options: &Options) -> Result<(), String> {
+    let path = Path::new(&options.scene);
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    let scene = match extension
+        .map(|extension| extension.to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("gltf" | "glb") => gltf::load(path).map_err(|error| error.to_string()),
+        _ => scene_file::load(path).map_err(|error| error.to_string()),
+    }
+    .map_err(|error| format!("{}: {error}", options.scene))?;
+
+    // Models can be shared between instances, so warn once per model
+    let mut warned: Vec<*const Model> = vec![];
+    for instance in &scene.instances {
+        let instance = instance.borrow();
+        let model: *const Model = instance.get_model();
+        if !warned.contains(&model) {
+            warned.push(model);
+            for warning in &instance.get_model().warnings {
+                eprintln!("render: {}: warning: {warning}", options.scene);
+            }
+        }
+    }
+
+    let canvas = Canvas::headless(options.width, options.height);
+    // Same field of view as the interactive window, stretched to the aspect ratio
+    let viewport_height = 7.2;
+    let aspect = options.width as f32 / options.height as f32;
+    let mut viewport = Viewport::new(viewport_height * aspect, viewport_height, 6.0, &canvas);
+    viewport.set_translation(&options.camera);
+    viewport.set_rotation(&options.camera_rotation);
+
+    let mut renderer = Renderer::new(canvas, viewport);
+    renderer.render_mode = options.mode;
+    renderer.canvas.clear(color::BLACK);
+    renderer.render_scene(&scene);
+
+    renderer
+        .canvas
+        .save(&options.output)
+        .map_err(|error| format!("{}: {error}", options.output))
+}
+
+fn main() -> ExitCode {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("render: {error}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+
+    match render(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("render: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,4 +1,6 @@
-#[derive(Copy, Clone)]
+use nalgebra_glm::Vec3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -13,6 +15,16 @@ impl Color {
             b: b.clamp(0, 255),
         }
     }
+
+    /// Blends towards `other` by `t` (0 is self, 1 is other)
+    pub fn lerp(self, other: Color, t: f32) -> Color {
+        let mix = |a: u8, b: u8| (a as f32 + t * (b as f32 - a as f32)).round() as u8;
+        Color::new(
+            mix(self.r, other.r),
+            mix(self.g, other.g),
+            mix(self.b, other.b),
+        )
+    }
 }
 
 impl std::ops::Mul<Color> for f32 {
@@ -37,9 +49,51 @@ impl std::ops::Mul<f32> for Color {
     }
 }
 
-impl Into<sdl2::pixels::Color> for Color {
-    fn into(self) -> sdl2::pixels::Color {
-        sdl2::pixels::Color::RGB(self.r, self.g, self.b)
+/// Scales each channel by the matching component
+impl std::ops::Mul<Vec3> for Color {
+    type Output = Color;
+    fn mul(self, factors: Vec3) -> Self::Output {
+        Self::Output::new(
+            ((self.r as f32) * factors.x) as u8,
+            ((self.g as f32) * factors.y) as u8,
+            ((self.b as f32) * factors.z) as u8,
+        )
+    }
+}
+
+/// Channels as floats between 0 and 1
+impl From<Color> for Vec3 {
+    fn from(color: Color) -> Self {
+        Vec3::new(color.r as f32, color.g as f32, color.b as f32) / 255.0
+    }
+}
+
+/// Channels from floats between 0 and 1, saturating outside of that
+impl From<Vec3> for Color {
+    fn from(rgb: Vec3) -> Self {
+        let channel = |value: f32| (value * 255.0).round() as u8;
+        Color::new(channel(rgb.x), channel(rgb.y), channel(rgb.z))
+    }
+}
+
+#[cfg(feature = "sdl")]
+impl From<Color> for sdl2::pixels::Color {
+    fn from(color: Color) -> Self {
+        sdl2::pixels::Color::RGB(color.r, color.g, color.b)
+    }
+}
+
+/// Packs into `0x00RRGGBB`
+impl From<Color> for u32 {
+    fn from(color: Color) -> Self {
+        ((color.r as u32) << 16) | ((color.g as u32) << 8) | (color.b as u32)
+    }
+}
+
+/// Unpacks from `0x00RRGGBB`
+impl From<u32> for Color {
+    fn from(pixel: u32) -> Self {
+        Color::new((pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8)
     }
 }
 
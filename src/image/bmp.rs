@@ -0,0 +1,158 @@
+//! Windows BMP images with 24 or 32 bits per pixel.
+
+use super::{Image, ImageError, Reader};
+use crate::color::Color;
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+pub fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+    let mut reader = Reader::new(bytes);
+    if reader.take(2)? != b"BM" {
+        return reader.malformed("expected BM signature");
+    }
+    let _file_len = reader.u32_le()?;
+    let _reserved = reader.u32_le()?;
+    let data_offset = reader.u32_le()? as usize;
+
+    let header_start = reader.offset;
+    let header_len = reader.u32_le()? as usize;
+    if header_len < 40 {
+        reader.seek(header_start)?;
+        return reader.unsupported(format!("{header_len} byte info header"));
+    }
+    let width = reader.i32_le()?;
+    let height = reader.i32_le()?;
+    if width < 0 {
+        reader.seek(header_start + 4)?;
+        return reader.malformed(format!("negative width {width}"));
+    }
+    let _planes = reader.u16_le()?;
+    let depth_offset = reader.offset;
+    let depth = reader.u16_le()?;
+    let compression_offset = reader.offset;
+    let compression = reader.u32_le()?;
+
+    // Channel masks for 32-bit images. Plain RGB stores them as BGRX.
+    let masks = match (depth, compression) {
+        (24, BI_RGB) => None,
+        (32, BI_RGB) => Some([0x00ff0000, 0x0000ff00, 0x000000ff]),
+        (32, BI_BITFIELDS) | (32, BI_ALPHABITFIELDS) => {
+            // Masks follow the 40 byte header, inside it or after it
+            reader.seek(header_start + 40)?;
+            Some([reader.u32_le()?, reader.u32_le()?, reader.u32_le()?])
+        }
+        (24, _) | (32, _) => {
+            reader.seek(compression_offset)?;
+            return reader.unsupported(format!("compression method {compression}"));
+        }
+        _ => {
+            reader.seek(depth_offset)?;
+            return reader.unsupported(format!("{depth} bits per pixel"));
+        }
+    };
+
+    // Positive heights are stored bottom up
+    let bottom_up = height > 0;
+    let width = width as usize;
+    let height = height.unsigned_abs() as usize;
+    let count = reader.pixel_count(width, height)?;
+    let pixel_len = depth as usize / 8;
+    // Rows are padded to a multiple of four bytes
+    let row_len = (width * pixel_len).div_ceil(4) * 4;
+
+    reader.seek(data_offset)?;
+    let mut rows = Vec::with_capacity(height.min(reader.remaining()));
+    for _ in 0..height {
+        let row = reader.take(row_len)?;
+        rows.push(&row[..width * pixel_len]);
+    }
+    if bottom_up {
+        rows.reverse();
+    }
+
+    let mut pixels = Vec::with_capacity(count);
+    for row in rows {
+        for raw in row.chunks(pixel_len) {
+            pixels.push(match masks {
+                None => Color::new(raw[2], raw[1], raw[0]),
+                Some([r, g, b]) => {
+                    let value = u32::from_le_bytes(raw.try_into().unwrap());
+                    Color::new(channel(value, r), channel(value, g), channel(value, b))
+                }
+            });
+        }
+    }
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Extracts the bits of a channel and scales them to 8 bits
+fn channel(value: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let max = mask >> mask.trailing_zeros();
+    (((value & mask) >> mask.trailing_zeros()) as u64 * 255 / max as u64) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 24-bit 2x2 image, stored bottom up with rows padded to 8 bytes
+    fn bytes(width: i32, height: i32) -> Vec<u8> {
+        let mut bytes = b"BM".to_vec();
+        bytes.extend(70u32.to_le_bytes());
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend(54u32.to_le_bytes());
+        bytes.extend(40u32.to_le_bytes());
+        bytes.extend(width.to_le_bytes());
+        bytes.extend(height.to_le_bytes());
+        bytes.extend(1u16.to_le_bytes());
+        bytes.extend(24u16.to_le_bytes());
+        bytes.extend(BI_RGB.to_le_bytes());
+        bytes.extend([0; 20]);
+        // Bottom row, then top row, as BGR
+        bytes.extend([0, 0, 255, 0, 255, 0, 0, 0]);
+        bytes.extend([255, 0, 0, 1, 2, 3, 0, 0]);
+        bytes
+    }
+
+    #[test]
+    fn decodes_bottom_up_rows() {
+        let image = decode(&bytes(2, 2)).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(
+            image.pixels,
+            vec![
+                Color::new(0, 0, 255),
+                Color::new(3, 2, 1),
+                Color::new(255, 0, 0),
+                Color::new(0, 255, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn negative_height_is_top_down() {
+        let image = decode(&bytes(2, -2)).unwrap();
+        assert_eq!(image.pixels[0], Color::new(255, 0, 0));
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        assert!(decode(&bytes(0, 2)).is_err());
+        assert!(decode(&bytes(2, 0)).is_err());
+        assert!(decode(&bytes(-2, 2)).is_err());
+        let bytes = bytes(2, 2);
+        for len in 0..bytes.len() {
+            assert!(decode(&bytes[..len]).is_err(), "{len} bytes decoded");
+        }
+    }
+}
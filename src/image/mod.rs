@@ -0,0 +1,206 @@
+//! Decoders turning image files into pixels a [`Texture`] can use, and
+//! encoders for saving what we rendered.
+
+pub mod bmp;
+pub mod png;
+pub mod ppm;
+pub mod tga;
+
+use crate::color::Color;
+use crate::rendering::texture::Texture;
+use nalgebra_glm::Vec3;
+use std::fmt;
+use std::path::Path;
+
+/// Decoded pixels, row-major with the top left first.
+///
+/// Alpha channels are dropped while decoding.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn into_texture(self) -> Texture {
+        Texture::new(self.width, self.height, self.pixels)
+    }
+
+    /// Multiplies every pixel by given color, channel by channel
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        let tint = Vec3::from(tint);
+        for pixel in &mut self.pixels {
+            *pixel = Color::from(Vec3::from(*pixel).component_mul(&tint));
+        }
+        self
+    }
+
+    /// Packs the pixels as `[r, g, b, r, g, b, ...]`
+    pub fn to_rgb(&self) -> Vec<u8> {
+        self.pixels.iter().flat_map(|c| [c.r, c.g, c.b]).collect()
+    }
+
+    /// Packs the pixels as `[r, g, b, a, ...]` with every pixel fully opaque
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|c| [c.r, c.g, c.b, 255])
+            .collect()
+    }
+
+    /// Writes the image, picking PNG or PPM from the file extension
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ImageError> {
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        let bytes = match extension.map(|extension| extension.to_ascii_lowercase()) {
+            Some(extension) if extension == "png" => png::encode(self),
+            Some(extension) if extension == "ppm" => ppm::encode(self),
+            _ => return Err(ImageError::UnknownFormat),
+        };
+        std::fs::write(path, bytes)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(std::io::Error),
+    /// The data does not start like any format we know, or we cannot tell
+    /// which format to save from the file name
+    UnknownFormat,
+    /// The data breaks the rules of its format
+    Malformed {
+        offset: usize,
+        reason: String,
+    },
+    /// The data is valid but uses a feature we cannot decode
+    Unsupported {
+        offset: usize,
+        feature: String,
+    },
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(error) => write!(f, "{error}"),
+            ImageError::UnknownFormat => write!(f, "unknown image format"),
+            ImageError::Malformed { offset, reason } => {
+                write!(f, "malformed image at byte {offset}: {reason}")
+            }
+            ImageError::Unsupported { offset, feature } => {
+                write!(f, "unsupported image feature at byte {offset}: {feature}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+impl From<std::io::Error> for ImageError {
+    fn from(error: std::io::Error) -> Self {
+        ImageError::Io(error)
+    }
+}
+
+/// Reads and decodes an image file
+pub fn load(path: impl AsRef<Path>) -> Result<Image, ImageError> {
+    decode(&std::fs::read(path)?)
+}
+
+/// Decodes an image, guessing the format from its first bytes.
+///
+/// TGA files have no signature, so anything unrecognised is tried as TGA.
+pub fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+    if bytes.starts_with(&png::SIGNATURE) {
+        png::decode(bytes)
+    } else if bytes.starts_with(b"BM") {
+        bmp::decode(bytes)
+    } else if bytes.starts_with(b"P3") || bytes.starts_with(b"P6") {
+        ppm::decode(bytes)
+    } else if bytes.len() >= tga::HEADER_LEN {
+        tga::decode(bytes)
+    } else {
+        Err(ImageError::UnknownFormat)
+    }
+}
+
+/// Largest image we agree to decode, in pixels
+const MAX_PIXELS: usize = 1 << 28;
+
+/// Walks through a byte slice remembering where it is for error messages
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    fn malformed<T>(&self, reason: impl Into<String>) -> Result<T, ImageError> {
+        Err(ImageError::Malformed {
+            offset: self.offset,
+            reason: reason.into(),
+        })
+    }
+
+    fn unsupported<T>(&self, feature: impl Into<String>) -> Result<T, ImageError> {
+        Err(ImageError::Unsupported {
+            offset: self.offset,
+            feature: feature.into(),
+        })
+    }
+
+    /// Multiplies out the image size, refusing empty images and sizes that
+    /// cannot possibly fit in memory
+    fn pixel_count(&self, width: usize, height: usize) -> Result<usize, ImageError> {
+        match width.checked_mul(height) {
+            Some(0) => self.malformed(format!("empty image size {width}x{height}")),
+            Some(count) if count <= MAX_PIXELS => Ok(count),
+            _ => self.malformed(format!("image size {width}x{height} is too large")),
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.offset
+    }
+
+    fn seek(&mut self, offset: usize) -> Result<(), ImageError> {
+        if offset > self.bytes.len() {
+            return self.malformed(format!("offset {offset} is past the end of the file"));
+        }
+        self.offset = offset;
+        Ok(())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ImageError> {
+        if self.remaining() < len {
+            return self.malformed("unexpected end of file");
+        }
+        let bytes = &self.bytes[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, ImageError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16_le(&mut self) -> Result<u16, ImageError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32_le(&mut self) -> Result<u32, ImageError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32_le(&mut self) -> Result<i32, ImageError> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u32_be(&mut self) -> Result<u32, ImageError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
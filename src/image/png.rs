@@ -0,0 +1,435 @@
+//! PNG images in every standard color type and bit depth, interlaced or not.
+//! Encoding always produces 8-bit RGB.
+
+use super::{Image, ImageError, Reader};
+use crate::color::Color;
+
+pub const SIGNATURE: [u8; 8] = [137, b'P', b'N', b'G', b'\r', b'\n', 26, b'\n'];
+
+/// Where each Adam7 pass starts and how far apart its pixels are: (x, y, dx, dy)
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// What the image header tells us about the pixel data
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            0 | 3 => 1,
+            4 => 2,
+            2 => 3,
+            _ => 4,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    /// Bytes in a row of `width` pixels, not counting the filter type
+    fn stride(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    /// Every pass as (x, y, dx, dy, width, height). Just one without interlacing.
+    fn passes(&self) -> Vec<(usize, usize, usize, usize, usize, usize)> {
+        if !self.interlaced {
+            return vec![(0, 0, 1, 1, self.width, self.height)];
+        }
+        ADAM7
+            .iter()
+            .map(|&(x, y, dx, dy)| {
+                let width = (self.width + dx - 1 - x) / dx;
+                let height = (self.height + dy - 1 - y) / dy;
+                (x, y, dx, dy, width, height)
+            })
+            .filter(|&(.., width, height)| width > 0 && height > 0)
+            .collect()
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+    let mut reader = Reader::new(bytes);
+    if reader.take(SIGNATURE.len())? != SIGNATURE {
+        reader.seek(0)?;
+        return reader.malformed("expected PNG signature");
+    }
+
+    let mut header: Option<Header> = None;
+    let mut palette: Vec<Color> = vec![];
+    let mut compressed: Vec<u8> = vec![];
+    let mut data_offset = 0;
+    loop {
+        let chunk_offset = reader.offset;
+        let len = reader.u32_be()? as usize;
+        let kind_and_data = reader.take(4 + len)?;
+        let crc = reader.u32_be()?;
+        if crc32(kind_and_data) != crc {
+            reader.seek(chunk_offset)?;
+            return reader.malformed("chunk checksum mismatch");
+        }
+        let (kind, data) = kind_and_data.split_at(4);
+        let data_start = chunk_offset + 8;
+
+        if header.is_none() && kind != b"IHDR" {
+            reader.seek(chunk_offset)?;
+            return reader.malformed("first chunk is not IHDR");
+        }
+        match kind {
+            b"IHDR" => {
+                reader.seek(data_start)?;
+                header = Some(read_header(&mut reader, len)?);
+                reader.seek(data_start + len + 4)?;
+            }
+            b"PLTE" => {
+                if !len.is_multiple_of(3) || len > 256 * 3 {
+                    reader.seek(chunk_offset)?;
+                    return reader.malformed(format!("palette of {len} bytes"));
+                }
+                palette = data
+                    .chunks(3)
+                    .map(|rgb| Color::new(rgb[0], rgb[1], rgb[2]))
+                    .collect();
+            }
+            b"IDAT" => {
+                if compressed.is_empty() {
+                    data_offset = data_start;
+                }
+                compressed.extend_from_slice(data);
+            }
+            b"IEND" => break,
+            _ => {
+                // Lowercase first letter means we can safely skip the chunk
+                if kind[0].is_ascii_uppercase() {
+                    reader.seek(chunk_offset + 4)?;
+                    return reader
+                        .unsupported(format!("critical chunk {}", String::from_utf8_lossy(kind)));
+                }
+            }
+        }
+    }
+
+    let header = header.unwrap();
+    if header.color_type == 3 && palette.is_empty() {
+        return reader.malformed("indexed image without a palette");
+    }
+    reader.pixel_count(header.width, header.height)?;
+
+    let passes = header.passes();
+    let expected: usize = passes
+        .iter()
+        .map(|&(.., width, height)| (header.stride(width) + 1) * height)
+        .sum();
+    reader.seek(data_offset)?;
+    let filtered =
+        match miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(&compressed, expected) {
+            Ok(filtered) => filtered,
+            Err(error) => {
+                return reader.malformed(format!("image data failed to inflate: {error}"))
+            }
+        };
+    if filtered.len() != expected {
+        return reader.malformed(format!(
+            "image data inflates to {} bytes instead of {expected}",
+            filtered.len()
+        ));
+    }
+
+    let mut pixels = vec![Color::new(0, 0, 0); header.width * header.height];
+    let mut start = 0;
+    for (x0, y0, dx, dy, width, height) in passes {
+        let stride = header.stride(width);
+        let rows = unfilter(
+            &filtered[start..start + (stride + 1) * height],
+            stride,
+            header.bits_per_pixel().div_ceil(8),
+        )
+        .map_err(|reason| ImageError::Malformed {
+            offset: data_offset,
+            reason,
+        })?;
+        start += (stride + 1) * height;
+
+        for (y, row) in rows.chunks(stride).enumerate() {
+            for x in 0..width {
+                let color =
+                    pixel(&header, &palette, row, x).map_err(|reason| ImageError::Malformed {
+                        offset: data_offset,
+                        reason,
+                    })?;
+                pixels[(y0 + y * dy) * header.width + x0 + x * dx] = color;
+            }
+        }
+    }
+
+    Ok(Image {
+        width: header.width,
+        height: header.height,
+        pixels,
+    })
+}
+
+fn read_header(reader: &mut Reader, len: usize) -> Result<Header, ImageError> {
+    if len != 13 {
+        return reader.malformed(format!("IHDR of {len} bytes instead of 13"));
+    }
+    let width = reader.u32_be()? as usize;
+    let height = reader.u32_be()? as usize;
+    if width == 0 || height == 0 {
+        reader.seek(reader.offset - 8)?;
+        return reader.malformed(format!("empty image size {width}x{height}"));
+    }
+    let bit_depth = reader.u8()?;
+    let color_type = reader.u8()?;
+    let valid = match color_type {
+        0 => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+        3 => matches!(bit_depth, 1 | 2 | 4 | 8),
+        2 | 4 | 6 => matches!(bit_depth, 8 | 16),
+        _ => false,
+    };
+    if !valid {
+        reader.seek(reader.offset - 2)?;
+        return reader.malformed(format!(
+            "bit depth {bit_depth} with color type {color_type}"
+        ));
+    }
+    if reader.u8()? != 0 {
+        reader.seek(reader.offset - 1)?;
+        return reader.unsupported("compression method other than deflate");
+    }
+    if reader.u8()? != 0 {
+        reader.seek(reader.offset - 1)?;
+        return reader.unsupported("filter method other than adaptive");
+    }
+    let interlaced = match reader.u8()? {
+        0 => false,
+        1 => true,
+        method => {
+            reader.seek(reader.offset - 1)?;
+            return reader.malformed(format!("unknown interlace method {method}"));
+        }
+    };
+
+    Ok(Header {
+        width,
+        height,
+        bit_depth,
+        color_type,
+        interlaced,
+    })
+}
+
+/// Undoes the per-row filters, returning the rows without their filter type byte
+fn unfilter(data: &[u8], stride: usize, pixel_len: usize) -> Result<Vec<u8>, String> {
+    let mut rows = vec![0u8; data.len() - data.len() / (stride + 1)];
+    let mut previous = vec![0u8; stride];
+    for (y, filtered) in data.chunks(stride + 1).enumerate() {
+        let (filter, filtered) = (filtered[0], &filtered[1..]);
+        let row = &mut rows[y * stride..(y + 1) * stride];
+        for x in 0..stride {
+            let a = if x >= pixel_len {
+                row[x - pixel_len]
+            } else {
+                0
+            };
+            let b = previous[x];
+            let c = if x >= pixel_len {
+                previous[x - pixel_len]
+            } else {
+                0
+            };
+            let predicted = match filter {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(format!("unknown filter type {filter} on row {y}")),
+            };
+            row[x] = filtered[x].wrapping_add(predicted);
+        }
+        previous.copy_from_slice(row);
+    }
+
+    Ok(rows)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Reads pixel `x` of an unfiltered row
+fn pixel(header: &Header, palette: &[Color], row: &[u8], x: usize) -> Result<Color, String> {
+    let depth = header.bit_depth as usize;
+    // Reads channel `i` of the pixel scaled to 8 bits (or raw for palette indices)
+    let sample = |i: usize| -> u8 {
+        let bit = (x * header.channels() + i) * depth;
+        match depth {
+            // The high byte of a 16-bit sample is the first one
+            8 | 16 => row[bit / 8],
+            _ => {
+                let value = (row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1);
+                if header.color_type == 3 {
+                    value
+                } else {
+                    (value as usize * 255 / ((1 << depth) - 1)) as u8
+                }
+            }
+        }
+    };
+
+    Ok(match header.color_type {
+        0 | 4 => Color::new(sample(0), sample(0), sample(0)),
+        2 | 6 => Color::new(sample(0), sample(1), sample(2)),
+        _ => {
+            let index = sample(0) as usize;
+            match palette.get(index) {
+                Some(color) => *color,
+                None => return Err(format!("palette index {index} out of range")),
+            }
+        }
+    })
+}
+
+/// Encodes an image as 8-bit RGB PNG.
+///
+/// Every row uses the Paeth filter, which suits rendered images well.
+pub fn encode(image: &Image) -> Vec<u8> {
+    let stride = image.width * 3;
+    let rgb = image.to_rgb();
+    let mut filtered = Vec::with_capacity((stride + 1) * image.height);
+    for y in 0..image.height {
+        let row = &rgb[y * stride..(y + 1) * stride];
+        filtered.push(4);
+        for x in 0..stride {
+            let a = if x >= 3 { row[x - 3] } else { 0 };
+            let (b, c) = match y {
+                0 => (0, 0),
+                _ => {
+                    let previous = &rgb[(y - 1) * stride..y * stride];
+                    (previous[x], if x >= 3 { previous[x - 3] } else { 0 })
+                }
+            };
+            filtered.push(row[x].wrapping_sub(paeth(a, b, c)));
+        }
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend((image.width as u32).to_be_bytes());
+    header.extend((image.height as u32).to_be_bytes());
+    // 8 bits per channel, RGB, deflate, adaptive filtering, no interlacing
+    header.extend([8, 2, 0, 0, 0]);
+
+    let mut bytes = SIGNATURE.to_vec();
+    write_chunk(&mut bytes, b"IHDR", &header);
+    write_chunk(
+        &mut bytes,
+        b"IDAT",
+        &miniz_oxide::deflate::compress_to_vec_zlib(&filtered, 6),
+    );
+    write_chunk(&mut bytes, b"IEND", &[]);
+    bytes
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    bytes.extend((data.len() as u32).to_be_bytes());
+    let start = bytes.len();
+    bytes.extend(kind);
+    bytes.extend(data);
+    let crc = crc32(&bytes[start..]);
+    bytes.extend(crc.to_be_bytes());
+}
+
+/// CRC-32 as used by PNG chunks
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image() -> Image {
+        Image {
+            width: 3,
+            height: 2,
+            pixels: vec![
+                Color::new(255, 0, 0),
+                Color::new(0, 255, 0),
+                Color::new(0, 0, 255),
+                Color::new(10, 20, 30),
+                Color::new(200, 100, 50),
+                Color::new(255, 255, 255),
+            ],
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let decoded = decode(&encode(&image())).unwrap();
+        assert_eq!((decoded.width, decoded.height), (3, 2));
+        assert_eq!(decoded.pixels, image().pixels);
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let bytes = encode(&image());
+        for len in 0..bytes.len() {
+            assert!(decode(&bytes[..len]).is_err(), "{len} bytes decoded");
+        }
+    }
+
+    #[test]
+    fn corrupted_chunk_is_an_error() {
+        let mut bytes = encode(&image());
+        // First byte of the IHDR data
+        bytes[16] ^= 1;
+        assert!(matches!(decode(&bytes), Err(ImageError::Malformed { .. })));
+    }
+
+    #[test]
+    fn empty_image_is_an_error() {
+        let mut bytes = SIGNATURE.to_vec();
+        let mut header = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        header.extend([8, 2, 0, 0, 0]);
+        write_chunk(&mut bytes, b"IHDR", &header);
+        assert!(matches!(decode(&bytes), Err(ImageError::Malformed { .. })));
+    }
+}
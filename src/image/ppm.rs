@@ -0,0 +1,159 @@
+//! Netpbm PPM images, both ASCII (`P3`) and binary (`P6`).
+
+use super::{Image, ImageError, Reader};
+use crate::color::Color;
+
+pub fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+    let mut reader = Reader::new(bytes);
+    let binary = match reader.take(2)? {
+        b"P3" => false,
+        b"P6" => true,
+        _ => return reader.malformed("expected P3 or P6 magic number"),
+    };
+
+    let width = header_value(&mut reader)? as usize;
+    let height = header_value(&mut reader)? as usize;
+    let max_value = header_value(&mut reader)?;
+    if max_value == 0 || max_value > 65535 {
+        return reader.malformed(format!(
+            "maximum value {max_value} is not between 1 and 65535"
+        ));
+    }
+
+    let sample_count = reader.pixel_count(width, height)? * 3;
+    let mut samples = Vec::with_capacity(sample_count.min(reader.remaining()));
+    if binary {
+        // Exactly one whitespace character separates the header from the data
+        if !reader.u8()?.is_ascii_whitespace() {
+            return reader.malformed("expected whitespace before pixel data");
+        }
+        let sample_len = if max_value < 256 { 1 } else { 2 };
+        let data = reader.take(sample_count * sample_len)?;
+        for sample in data.chunks(sample_len) {
+            samples.push(match sample {
+                [value] => *value as u32,
+                [high, low] => u16::from_be_bytes([*high, *low]) as u32,
+                _ => unreachable!(),
+            });
+        }
+    } else {
+        for _ in 0..sample_count {
+            samples.push(ascii_value(&mut reader)?);
+        }
+    }
+
+    let scale = |value: u32| (value.min(max_value) * 255 / max_value) as u8;
+    let pixels = samples
+        .chunks(3)
+        .map(|rgb| Color::new(scale(rgb[0]), scale(rgb[1]), scale(rgb[2])))
+        .collect();
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Reads a header number, skipping whitespace and `#` comments before it
+fn header_value(reader: &mut Reader) -> Result<u32, ImageError> {
+    loop {
+        match reader.bytes.get(reader.offset) {
+            Some(b'#') => while reader.u8()? != b'\n' {},
+            Some(byte) if byte.is_ascii_whitespace() => reader.offset += 1,
+            _ => break,
+        }
+    }
+    ascii_value(reader)
+}
+
+/// Reads a decimal number, skipping whitespace before it
+fn ascii_value(reader: &mut Reader) -> Result<u32, ImageError> {
+    while reader
+        .bytes
+        .get(reader.offset)
+        .is_some_and(|byte| byte.is_ascii_whitespace())
+    {
+        reader.offset += 1;
+    }
+    let start = reader.offset;
+    while reader
+        .bytes
+        .get(reader.offset)
+        .is_some_and(|byte| byte.is_ascii_digit())
+    {
+        reader.offset += 1;
+    }
+    if start == reader.offset {
+        return match reader.bytes.get(start) {
+            None => reader.malformed("unexpected end of file"),
+            Some(_) => reader.malformed("expected a number"),
+        };
+    }
+
+    let digits = std::str::from_utf8(&reader.bytes[start..reader.offset]).unwrap();
+    match digits.parse() {
+        Ok(value) => Ok(value),
+        Err(_) => {
+            reader.offset = start;
+            reader.malformed(format!("number {digits} is too large"))
+        }
+    }
+}
+
+/// Encodes an image as binary PPM (`P6`)
+pub fn encode(image: &Image) -> Vec<u8> {
+    let mut bytes = format!("P6\n{} {}\n255\n", image.width, image.height).into_bytes();
+    bytes.extend(image.to_rgb());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let image = Image {
+            width: 2,
+            height: 2,
+            pixels: vec![
+                Color::new(1, 2, 3),
+                Color::new(255, 0, 128),
+                Color::new(0, 0, 0),
+                Color::new(9, 99, 199),
+            ],
+        };
+        let decoded = decode(&encode(&image)).unwrap();
+        assert_eq!((decoded.width, decoded.height), (2, 2));
+        assert_eq!(decoded.pixels, image.pixels);
+    }
+
+    #[test]
+    fn ascii_with_comments_and_scaling() {
+        let decoded = decode(b"P3 # comment\n2 1\n# another\n15\n15 0 0  0 15 3\n").unwrap();
+        assert_eq!(
+            decoded.pixels,
+            vec![Color::new(255, 0, 0), Color::new(0, 255, 51)]
+        );
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        for bytes in [
+            &b"P6\n0 1\n255\n"[..],
+            b"P6\n1 1\n0\n\x00\x00\x00",
+            b"P6\n1 1\n255\nab",
+            b"P3\n1 1\n255\n1 2",
+            b"P3\n1 1\n255\n1 x 3",
+            b"P3\n99999999999 1\n255\n",
+            b"P6\n4294967295 4294967295\n255\n",
+        ] {
+            assert!(
+                decode(bytes).is_err(),
+                "{:?}",
+                String::from_utf8_lossy(bytes)
+            );
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Truevision TGA images, uncompressed or run-length encoded, in true color
+//! or grayscale.
+
+use super::{Image, ImageError, Reader};
+use crate::color::Color;
+
+pub const HEADER_LEN: usize = 18;
+
+pub fn decode(bytes: &[u8]) -> Result<Image, ImageError> {
+    let mut reader = Reader::new(bytes);
+    let id_len = reader.u8()? as usize;
+    let color_map_type = reader.u8()?;
+    let image_type = reader.u8()?;
+    let _color_map_first = reader.u16_le()?;
+    let color_map_len = reader.u16_le()? as usize;
+    let color_map_depth = reader.u8()? as usize;
+    let _x_origin = reader.u16_le()?;
+    let _y_origin = reader.u16_le()?;
+    let width = reader.u16_le()? as usize;
+    let height = reader.u16_le()? as usize;
+    let depth = reader.u8()?;
+    let descriptor = reader.u8()?;
+
+    let (grayscale, rle) = match image_type {
+        2 => (false, false),
+        3 => (true, false),
+        10 => (false, true),
+        11 => (true, true),
+        1 | 9 => {
+            reader.seek(2)?;
+            return reader.unsupported("color mapped TGA");
+        }
+        _ => {
+            reader.seek(2)?;
+            return reader.malformed(format!("unknown image type {image_type}"));
+        }
+    };
+    match (grayscale, depth) {
+        (true, 8) | (false, 16) | (false, 24) | (false, 32) => {}
+        _ => {
+            reader.seek(16)?;
+            return reader.unsupported(format!("{depth} bits per pixel"));
+        }
+    }
+    if color_map_type > 1 {
+        reader.seek(1)?;
+        return reader.malformed(format!("unknown color map type {color_map_type}"));
+    }
+
+    // Skip the image id and any color map we do not need
+    reader.take(id_len)?;
+    if color_map_type == 1 {
+        reader.take(color_map_len * color_map_depth.div_ceil(8))?;
+    }
+
+    let pixel_len = depth as usize / 8;
+    let count = reader.pixel_count(width, height)?;
+    let mut pixels = Vec::with_capacity(count.min(reader.remaining()));
+    while pixels.len() < count {
+        if rle {
+            let packet = reader.u8()?;
+            let run = (packet & 0x7f) as usize + 1;
+            if run > count - pixels.len() {
+                reader.seek(reader.offset - 1)?;
+                return reader.malformed("run-length packet goes past the end of the image");
+            }
+            if packet & 0x80 != 0 {
+                let color = pixel(reader.take(pixel_len)?);
+                pixels.extend(std::iter::repeat_n(color, run));
+            } else {
+                for raw in reader.take(run * pixel_len)?.chunks(pixel_len) {
+                    pixels.push(pixel(raw));
+                }
+            }
+        } else {
+            for raw in reader.take(count * pixel_len)?.chunks(pixel_len) {
+                pixels.push(pixel(raw));
+            }
+        }
+    }
+
+    // Rows are stored bottom up unless the descriptor says otherwise
+    let mut rows: Vec<&[Color]> = pixels.chunks(width).collect();
+    if descriptor & 0x20 == 0 {
+        rows.reverse();
+    }
+    let right_to_left = descriptor & 0x10 != 0;
+    let pixels = rows
+        .into_iter()
+        .flat_map(|row| {
+            let mut row = row.to_vec();
+            if right_to_left {
+                row.reverse();
+            }
+            row
+        })
+        .collect();
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Decodes one stored pixel: gray, 5-5-5 BGR, BGR or BGRA
+fn pixel(raw: &[u8]) -> Color {
+    match raw {
+        [gray] => Color::new(*gray, *gray, *gray),
+        [low, high] => {
+            let value = u16::from_le_bytes([*low, *high]);
+            let channel = |shift: u16| (((value >> shift) & 0x1f) * 255 / 31) as u8;
+            Color::new(channel(10), channel(5), channel(0))
+        }
+        [b, g, r, ..] => Color::new(*r, *g, *b),
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(image_type: u8, width: u16, height: u16, depth: u8) -> Vec<u8> {
+        let mut bytes = vec![0, 0, image_type, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        bytes.extend(width.to_le_bytes());
+        bytes.extend(height.to_le_bytes());
+        // Top to bottom rows
+        bytes.extend([depth, 0x20]);
+        bytes
+    }
+
+    #[test]
+    fn decodes_uncompressed_true_color() {
+        let mut bytes = header(2, 2, 1, 24);
+        bytes.extend([0, 0, 255, 255, 0, 0]);
+        let image = decode(&bytes).unwrap();
+        assert_eq!(
+            image.pixels,
+            vec![Color::new(255, 0, 0), Color::new(0, 0, 255)]
+        );
+    }
+
+    #[test]
+    fn decodes_run_length_grayscale() {
+        let mut bytes = header(11, 2, 2, 8);
+        // A run of three, then one raw pixel
+        bytes.extend([0x82, 7, 0x00, 9]);
+        let image = decode(&bytes).unwrap();
+        let gray = |value| Color::new(value, value, value);
+        assert_eq!(image.pixels, vec![gray(7), gray(7), gray(7), gray(9)]);
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        assert!(decode(&header(2, 0, 1, 24)).is_err());
+        assert!(decode(&header(2, 1, 0, 24)).is_err());
+        assert!(decode(&header(7, 1, 1, 24)).is_err());
+
+        let mut too_long = header(11, 2, 1, 8);
+        too_long.extend([0x82, 7]);
+        assert!(decode(&too_long).is_err());
+
+        let mut bytes = header(2, 2, 1, 24);
+        bytes.extend([0, 0, 255, 255, 0, 0]);
+        for len in 0..bytes.len() {
+            assert!(decode(&bytes[..len]).is_err(), "{len} bytes decoded");
+        }
+    }
+}
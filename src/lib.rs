@@ -0,0 +1,6 @@
+pub mod color;
+pub mod image;
+pub mod models;
+pub mod rendering;
+
+mod text;
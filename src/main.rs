@@ -1,23 +1,19 @@
-#![allow(dead_code)]
-
-extern crate nalgebra_glm as glm;
-extern crate sdl2;
-
-mod color;
-mod models;
-mod rendering;
-
-use crate::models::model::{Instance, Model};
-use crate::rendering::canvas::Canvas;
-use crate::rendering::renderer::Renderer;
-use crate::rendering::scene::Scene;
-use crate::rendering::viewport::Viewport;
-use crate::sdl2::event::Event;
-use crate::sdl2::keyboard::Keycode;
-use crate::sdl2::EventPump;
-use nalgebra_glm::Vec4;
+use nalgebra_glm::{Vec3, Vec4};
+use rasterizer::color;
+use rasterizer::models;
+use rasterizer::models::model::{Instance, Model};
+use rasterizer::rendering::backend::SdlBackend;
+use rasterizer::rendering::canvas::Canvas;
+use rasterizer::rendering::light::Light;
+use rasterizer::rendering::renderer::Renderer;
+use rasterizer::rendering::scene::Scene;
+use rasterizer::rendering::viewport::Viewport;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::EventPump;
 use std::cell::RefCell;
 use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Produces a [`Canvas`] and an [`EventPump`]
 fn init_sdl(title: &str, width: u32, height: u32) -> (Canvas, EventPump) {
@@ -29,11 +25,28 @@ fn init_sdl(title: &str, width: u32, height: u32) -> (Canvas, EventPump) {
         .build()
         .unwrap();
     (
-        Canvas::new(window, width as i32, height as i32),
+        Canvas::new(
+            Box::new(SdlBackend::new(window)),
+            width as i32,
+            height as i32,
+        ),
         context.event_pump().unwrap(),
     )
 }
 
+/// Saves the last presented frame next to where we were started from
+fn save_screenshot(canvas: &Canvas) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let path = format!("screenshot-{timestamp}.png");
+    match canvas.save(&path) {
+        Ok(()) => println!("Saved {path}"),
+        Err(error) => eprintln!("Could not save {path}: {error}"),
+    }
+}
+
 fn main() {
     let (canvas, mut event_pump) = init_sdl("Rust SDL2", 1280, 720);
     let vp = Viewport::new(12.80, 7.20, 6.0, &canvas);
@@ -56,6 +69,19 @@ fn main() {
     // Add my instance to the scene and render the scene
     scene.add_instance(Rc::clone(&cube0));
 
+    // Light the scene
+    scene.add_light(Rc::new(RefCell::new(Light::ambient(0.2, color::WHITE))));
+    scene.add_light(Rc::new(RefCell::new(Light::point(
+        &Vec3::new(2.0, 1.0, 0.0),
+        0.6,
+        color::WHITE,
+    ))));
+    scene.add_light(Rc::new(RefCell::new(Light::directional(
+        &Vec3::new(1.0, 4.0, 4.0),
+        0.2,
+        color::WHITE,
+    ))));
+
     'running: loop {
         for event in event_pump.poll_iter() {
             match event {
@@ -64,6 +90,14 @@ fn main() {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    ..
+                } => renderer.render_mode = renderer.render_mode.next(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    ..
+                } => save_screenshot(&renderer.canvas),
                 _ => {}
             }
         }
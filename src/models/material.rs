@@ -0,0 +1,45 @@
+use crate::color;
+use crate::color::Color;
+use crate::rendering::texture::Texture;
+use std::rc::Rc;
+
+/// How a surface looks, as authored in a material library
+#[derive(Clone)]
+pub struct Material {
+    pub name: String,
+    /// Base color of the surface (`Kd`)
+    pub diffuse: Color,
+    /// Color of highlights (`Ks`). Black means no highlights.
+    pub specular: Color,
+    /// Specular exponent (`Ns`). Higher is shinier.
+    pub shininess: f32,
+    /// 1 is opaque, 0 fully transparent (`d`). Kept for exporting and
+    /// blending, the renderer draws every surface opaque for now.
+    pub opacity: f32,
+    /// Multiplied by the diffuse color where the model has texture
+    /// coordinates (`map_Kd`)
+    pub diffuse_map: Option<Rc<Texture>>,
+}
+
+impl Material {
+    /// A matte white material, what OBJ assumes when none is given
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            diffuse: color::WHITE,
+            specular: color::BLACK,
+            shininess: 0.0,
+            opacity: 1.0,
+            diffuse_map: None,
+        }
+    }
+
+    /// Specular exponent for lighting, `None` when the material has no highlights
+    pub fn get_specular_exponent(&self) -> Option<f32> {
+        if self.specular == color::BLACK || self.shininess <= 0.0 {
+            None
+        } else {
+            Some(self.shininess)
+        }
+    }
+}
@@ -1,2 +1,9 @@
+pub mod material;
 pub mod model;
+pub mod mtl;
+pub mod obj;
+pub mod ply;
+pub mod shapes;
+pub mod stl;
 pub mod triangle;
+pub mod vertex;
@@ -1,11 +1,454 @@
 use crate::color;
+use crate::color::Color;
 use crate::models::triangle::Triangle;
-use nalgebra_glm::{Mat4, Vec3, Vec4};
+use crate::models::vertex::{Vertex, VertexLayout};
+use nalgebra_glm::{quat_to_mat4, Mat4, Quat, Vec2, Vec3, Vec4};
+use std::collections::HashMap;
+use std::fmt;
 use std::rc::Rc;
 
+/// Smallest sphere we bother computing that contains every vertex of a model
+#[derive(Copy, Clone)]
+pub struct BoundingSphere {
+    pub center: Vec4,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Centers the sphere on the average of the points and grows it to reach the furthest one.
+    pub fn from_points(points: &[Vec4]) -> Self {
+        if points.is_empty() {
+            return Self {
+                center: Vec4::new(0.0, 0.0, 0.0, 1.0),
+                radius: 0.0,
+            };
+        }
+        let sum: Vec3 = points.iter().map(|point| point.xyz()).sum();
+        let center = sum / points.len() as f32;
+        let radius = points
+            .iter()
+            .map(|point| (point.xyz() - center).norm())
+            .fold(0.0, f32::max);
+
+        Self {
+            center: Vec4::new(center.x, center.y, center.z, 1.0),
+            radius,
+        }
+    }
+
+    /// Moves the sphere by given transform.
+    ///
+    /// The radius grows by the largest scale factor so the sphere still
+    /// contains the model under non-uniform scaling.
+    pub fn transform(&self, transform: &Mat4) -> Self {
+        let scale = (0..3)
+            .map(|i| transform.fixed_view::<3, 1>(0, i).norm())
+            .fold(0.0, f32::max);
+
+        Self {
+            center: transform * self.center,
+            radius: self.radius * scale,
+        }
+    }
+}
+
+/// How much each face contributes to the smoothed normal of its corners
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NormalWeighting {
+    /// Larger faces pull harder
+    Area,
+    /// Faces pull by the angle they span at the vertex
+    Angle,
+}
+
+/// Why a list of triangles cannot make up a model with given vertices. Every
+/// variant names the 0-based position of the offending triangle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModelError {
+    /// A corner refers to a vertex that does not exist
+    IndexOutOfRange {
+        triangle: usize,
+        index: i32,
+        vertex_count: usize,
+    },
+    /// The corners share a vertex, sit on the same spot or lie in a line, so
+    /// the triangle has no area
+    Degenerate { triangle: usize, vertices: [i32; 3] },
+    /// Same vertices in the same winding as an earlier triangle
+    Duplicate { triangle: usize, original: usize },
+}
+
+impl ModelError {
+    pub fn get_triangle(&self) -> usize {
+        match *self {
+            ModelError::IndexOutOfRange { triangle, .. }
+            | ModelError::Degenerate { triangle, .. }
+            | ModelError::Duplicate { triangle, .. } => triangle,
+        }
+    }
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelError::IndexOutOfRange {
+                triangle,
+                index,
+                vertex_count,
+            } => write!(
+                f,
+                "triangle {triangle} refers to vertex {index} of {vertex_count}"
+            ),
+            ModelError::Degenerate {
+                triangle,
+                vertices: [a, b, c],
+            } => write!(f, "triangle {triangle} has no area ({a}, {b}, {c})"),
+            ModelError::Duplicate { triangle, original } => {
+                write!(f, "triangle {triangle} repeats triangle {original}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ModelError {}
+
 pub struct Model {
-    pub vertices: Vec<Vec4>,
+    pub vertices: Vec<Vertex>,
     pub triangles: Vec<Triangle>,
+    /// Extra attributes carried by every vertex
+    pub layout: VertexLayout,
+    /// Per-triangle unit normals as authored, used instead of the normals
+    /// implied by the winding when generating vertex normals
+    pub facet_normals: Option<Vec<Vec3>>,
+    /// Problems a loader worked around, such as triangles it left out
+    pub warnings: Vec<String>,
+    bounding_sphere: BoundingSphere,
+}
+
+impl Model {
+    /// Trusts every triangle to refer to existing vertices. Use
+    /// [`Model::try_new`] for data from outside the program.
+    pub fn new(positions: Vec<Vec4>, triangles: Vec<Triangle>) -> Self {
+        let bounding_sphere = BoundingSphere::from_points(&positions);
+        Self {
+            vertices: positions.iter().map(Vertex::new).collect(),
+            triangles,
+            layout: VertexLayout::default(),
+            facet_normals: None,
+            warnings: vec![],
+            bounding_sphere,
+        }
+    }
+
+    /// Like [`Model::new`], but first checks that every corner refers to an
+    /// existing vertex, that every triangle has some area and that none
+    /// repeats an earlier one. The same vertices wound the other way make up
+    /// the back of a triangle and are allowed.
+    pub fn try_new(positions: Vec<Vec4>, triangles: Vec<Triangle>) -> Result<Self, ModelError> {
+        match validate(&positions, &triangles).into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(Self::new(positions, triangles)),
+        }
+    }
+
+    /// Like [`Model::try_new`], but leaves out triangles without area and
+    /// repeated ones instead of failing, noting how many in
+    /// [`Model::warnings`]. Also returns the positions the left out
+    /// triangles had in `triangles`, in order.
+    ///
+    /// Corners referring to missing vertices are still an error.
+    pub fn try_new_lenient(
+        positions: Vec<Vec4>,
+        triangles: Vec<Triangle>,
+    ) -> Result<(Self, Vec<usize>), ModelError> {
+        let mut skipped = vec![];
+        for error in validate(&positions, &triangles) {
+            match error {
+                ModelError::IndexOutOfRange { .. } => return Err(error),
+                _ => skipped.push(error.get_triangle()),
+            }
+        }
+
+        let mut next = skipped.iter().peekable();
+        let triangles = triangles
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| next.next_if_eq(&i).is_none())
+            .map(|(_, triangle)| triangle)
+            .collect();
+        let mut model = Self::new(positions, triangles);
+        if !skipped.is_empty() {
+            model.warnings.push(format!(
+                "skipped {} triangles without area or repeating another",
+                skipped.len()
+            ));
+        }
+        Ok((model, skipped))
+    }
+
+    /// Gives every vertex its own color. Needs one color per vertex.
+    pub fn with_colors(mut self, colors: Vec<Color>) -> Self {
+        assert_eq!(colors.len(), self.vertices.len(), "one color per vertex");
+        for (vertex, color) in self.vertices.iter_mut().zip(colors) {
+            vertex.color = Some(color);
+        }
+        self
+    }
+
+    /// Gives every vertex its own intensity. Needs one intensity per vertex.
+    pub fn with_intensities(mut self, intensities: Vec<f32>) -> Self {
+        assert_eq!(
+            intensities.len(),
+            self.vertices.len(),
+            "one intensity per vertex"
+        );
+        for (vertex, intensity) in self.vertices.iter_mut().zip(intensities) {
+            vertex.intensity = intensity;
+        }
+        self
+    }
+
+    /// Gives every vertex its own normal. Needs one normal per vertex.
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> Self {
+        assert_eq!(normals.len(), self.vertices.len(), "one normal per vertex");
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            vertex.normal = Some(normal);
+        }
+        self
+    }
+
+    /// Gives every vertex texture coordinates. Needs one pair per vertex.
+    pub fn with_uvs(mut self, uvs: Vec<Vec2>) -> Self {
+        assert_eq!(uvs.len(), self.vertices.len(), "one uv per vertex");
+        for (vertex, uv) in self.vertices.iter_mut().zip(uvs) {
+            vertex.uv = Some(uv);
+        }
+        self
+    }
+
+    /// Gives every vertex the values of an extra attribute, which gets
+    /// interpolated across triangles like the others. Needs `size` values
+    /// per vertex.
+    pub fn with_attribute(mut self, name: &str, size: usize, values: Vec<f32>) -> Self {
+        assert_eq!(
+            values.len(),
+            size * self.vertices.len(),
+            "{size} values per vertex"
+        );
+        for (vertex, values) in self.vertices.iter_mut().zip(values.chunks(size)) {
+            vertex.extra.extend_from_slice(values);
+        }
+        self.layout.push(name, size);
+        self
+    }
+
+    /// Gives every triangle its own normal. Needs one normal per triangle.
+    pub fn with_facet_normals(mut self, normals: Vec<Vec3>) -> Self {
+        assert_eq!(
+            normals.len(),
+            self.triangles.len(),
+            "one normal per triangle"
+        );
+        self.facet_normals = Some(normals);
+        self
+    }
+
+    /// Bounding sphere in model space, computed once on construction
+    pub fn get_bounding_sphere(&self) -> &BoundingSphere {
+        &self.bounding_sphere
+    }
+
+    /// Normal of every triangle following its winding.
+    ///
+    /// These are not normalized. Their length is twice the area of the triangle.
+    pub fn face_normals(&self) -> Vec<Vec3> {
+        self.triangles
+            .iter()
+            .map(|triangle| {
+                let [v0, v1, v2] = triangle
+                    .vertices
+                    .map(|i| self.vertices[i as usize].position.xyz());
+                (v1 - v0).cross(&(v2 - v0))
+            })
+            .collect()
+    }
+
+    /// Unit normal of every triangle, preferring [`Model::facet_normals`]
+    fn unit_face_normals(&self) -> Vec<Vec3> {
+        match &self.facet_normals {
+            Some(normals) => normals.clone(),
+            None => self.face_normals().iter().map(normalize_or_zero).collect(),
+        }
+    }
+
+    /// Gives every triangle corner the normal of its face.
+    ///
+    /// Vertices shared by faces pointing different ways get split so that
+    /// each face keeps its own.
+    pub fn compute_flat_normals(&mut self) {
+        let corner_normals = self
+            .unit_face_normals()
+            .into_iter()
+            .map(|normal| [normal; 3])
+            .collect();
+        self.apply_corner_normals(corner_normals);
+    }
+
+    /// Averages the normals of the faces meeting at each vertex.
+    ///
+    /// Faces whose normals are more than `crease_angle` radians apart do not
+    /// smooth into each other, so hard edges stay sharp. Vertices on such
+    /// edges get split like in [`Model::compute_flat_normals`].
+    pub fn compute_smooth_normals(&mut self, weighting: NormalWeighting, crease_angle: f32) {
+        let areas: Vec<f32> = self
+            .face_normals()
+            .iter()
+            .map(|normal| normal.norm())
+            .collect();
+        let unit_normals = self.unit_face_normals();
+
+        // Faces touching each vertex along with how much they pull on it
+        let mut adjacent: Vec<Vec<(usize, Vec3)>> = vec![vec![]; self.vertices.len()];
+        for (face, triangle) in self.triangles.iter().enumerate() {
+            for corner in 0..3 {
+                let weighted = match weighting {
+                    NormalWeighting::Area => unit_normals[face] * areas[face],
+                    NormalWeighting::Angle => {
+                        unit_normals[face] * self.corner_angle(triangle, corner)
+                    }
+                };
+                adjacent[triangle.vertices[corner] as usize].push((face, weighted));
+            }
+        }
+
+        let min_cos = crease_angle.cos();
+        let corner_normals = self
+            .triangles
+            .iter()
+            .enumerate()
+            .map(|(face, triangle)| {
+                triangle.vertices.map(|vertex| {
+                    let sum: Vec3 = adjacent[vertex as usize]
+                        .iter()
+                        .filter(|(other, _)| {
+                            unit_normals[face].dot(&unit_normals[*other]) >= min_cos
+                        })
+                        .map(|(_, weighted)| weighted)
+                        .sum();
+                    normalize_or_zero(&sum)
+                })
+            })
+            .collect();
+        self.apply_corner_normals(corner_normals);
+    }
+
+    /// Angle in radians the triangle spans at given corner
+    fn corner_angle(&self, triangle: &Triangle, corner: usize) -> f32 {
+        let position = |k: usize| {
+            self.vertices[triangle.vertices[k % 3] as usize]
+                .position
+                .xyz()
+        };
+        let e1 = position(corner + 1) - position(corner);
+        let e2 = position(corner + 2) - position(corner);
+        if e1.norm() == 0.0 || e2.norm() == 0.0 {
+            0.0
+        } else {
+            e1.angle(&e2)
+        }
+    }
+
+    /// Stores a normal for every triangle corner.
+    ///
+    /// Corners that share a vertex and a normal keep sharing a vertex, the
+    /// rest get a copy of the vertex with its other attributes.
+    fn apply_corner_normals(&mut self, corner_normals: Vec<[Vec3; 3]>) {
+        let mut vertices: Vec<Vertex> = vec![];
+        let mut seen: HashMap<(i32, [u32; 3]), i32> = HashMap::new();
+        for (triangle, corners) in self.triangles.iter_mut().zip(corner_normals) {
+            for (index, normal) in triangle.vertices.iter_mut().zip(corners) {
+                // Adding zero turns -0.0 into 0.0 so both share a vertex
+                let key = (
+                    *index,
+                    [normal.x + 0.0, normal.y + 0.0, normal.z + 0.0].map(f32::to_bits),
+                );
+                *index = *seen.entry(key).or_insert_with(|| {
+                    vertices.push(Vertex {
+                        normal: Some(normal),
+                        ..self.vertices[*index as usize].clone()
+                    });
+                    (vertices.len() - 1) as i32
+                });
+            }
+        }
+
+        self.vertices = vertices;
+        let positions: Vec<Vec4> = self.vertices.iter().map(|vertex| vertex.position).collect();
+        self.bounding_sphere = BoundingSphere::from_points(&positions);
+    }
+}
+
+/// Smallest sine of the angle between two edges of a triangle that still
+/// counts as having area
+const MIN_SINE: f32 = 1e-6;
+
+/// Every problem [`Model::try_new`] looks for, at most one per triangle and
+/// in the order of the triangles
+pub fn validate(positions: &[Vec4], triangles: &[Triangle]) -> Vec<ModelError> {
+    let vertex_count = positions.len();
+    let mut errors = vec![];
+    // Triangles keyed by their vertices rotated to start at the smallest
+    let mut seen: HashMap<[i32; 3], usize> = HashMap::new();
+    for (i, triangle) in triangles.iter().enumerate() {
+        let [a, b, c] = triangle.vertices;
+        if let Some(&index) = triangle
+            .vertices
+            .iter()
+            .find(|&&index| index < 0 || index as usize >= vertex_count)
+        {
+            errors.push(ModelError::IndexOutOfRange {
+                triangle: i,
+                index,
+                vertex_count,
+            });
+            continue;
+        }
+
+        let [p0, p1, p2] = triangle
+            .vertices
+            .map(|index| positions[index as usize].xyz());
+        let (e1, e2) = (p1 - p0, p2 - p0);
+        if e1.cross(&e2).norm() <= MIN_SINE * e1.norm() * e2.norm() {
+            errors.push(ModelError::Degenerate {
+                triangle: i,
+                vertices: triangle.vertices,
+            });
+            continue;
+        }
+
+        let key = match a.min(b).min(c) {
+            min if min == a => [a, b, c],
+            min if min == b => [b, c, a],
+            _ => [c, a, b],
+        };
+        match seen.get(&key) {
+            Some(&original) => errors.push(ModelError::Duplicate {
+                triangle: i,
+                original,
+            }),
+            None => {
+                seen.insert(key, i);
+            }
+        }
+    }
+
+    errors
+}
+
+fn normalize_or_zero(vector: &Vec3) -> Vec3 {
+    vector
+        .try_normalize(f32::EPSILON)
+        .unwrap_or_else(Vec3::zeros)
 }
 
 pub struct Instance {
@@ -13,7 +456,10 @@ pub struct Instance {
     scale: Mat4,
     rotation: Mat4,
     translation: Mat4,
+    /// Transform of whatever this instance hangs from, applied after its own
+    parent: Mat4,
     transformation: Mat4,
+    normal_transformation: Mat4,
 }
 
 impl Instance {
@@ -25,7 +471,9 @@ impl Instance {
                 * Mat4::new_rotation(-Vec3::y_axis().scale(rotation.y))
                 * Mat4::new_rotation(-Vec3::x_axis().scale(rotation.x)),
             translation: Mat4::new_translation(&translation.xyz()),
+            parent: Mat4::identity(),
             transformation: Mat4::identity(),
+            normal_transformation: Mat4::identity(),
         };
         instance.generate_transform();
 
@@ -56,6 +504,12 @@ impl Instance {
         self.generate_transform();
     }
 
+    /// Sets the rotation from a unit quaternion instead of Euler angles
+    pub fn set_rotation_quaternion(&mut self, rotation: &Quat) {
+        self.rotation = quat_to_mat4(rotation);
+        self.generate_transform();
+    }
+
     pub fn get_translation(&self) -> &Mat4 {
         &self.translation
     }
@@ -65,18 +519,38 @@ impl Instance {
         self.generate_transform();
     }
 
+    pub fn get_parent(&self) -> &Mat4 {
+        &self.parent
+    }
+
+    pub fn set_parent(&mut self, parent: &Mat4) {
+        self.parent = *parent;
+        self.generate_transform();
+    }
+
     pub fn generate_transform(&mut self) {
-        self.transformation = self.translation * self.rotation * self.scale;
+        self.transformation = self.parent * self.translation * self.rotation * self.scale;
+        // Normals need the inverse transpose to stay perpendicular under non-uniform scaling
+        self.normal_transformation = self
+            .transformation
+            .try_inverse()
+            .unwrap_or_else(Mat4::identity)
+            .transpose();
     }
 
     pub fn get_transform(&self) -> Mat4 {
         self.transformation
     }
+
+    /// Transform for normals (the inverse transpose of [`Instance::get_transform`])
+    pub fn get_normal_transform(&self) -> Mat4 {
+        self.normal_transformation
+    }
 }
 
 pub fn default_cube() -> Model {
-    Model {
-        vertices: vec![
+    Model::new(
+        vec![
             Vec4::new(1.0, 1.0, 1.0, 1.0),
             Vec4::new(-1.0, 1.0, 1.0, 1.0),
             Vec4::new(-1.0, -1.0, 1.0, 1.0),
@@ -86,7 +560,7 @@ pub fn default_cube() -> Model {
             Vec4::new(-1.0, -1.0, -1.0, 1.0),
             Vec4::new(1.0, -1.0, -1.0, 1.0),
         ],
-        triangles: vec![
+        vec![
             Triangle::new(0, 1, 2, color::RED),
             Triangle::new(0, 2, 3, color::RED),
             Triangle::new(4, 0, 3, color::GREEN),
@@ -100,5 +574,216 @@ pub fn default_cube() -> Model {
             Triangle::new(2, 6, 7, color::CYAN),
             Triangle::new(2, 7, 3, color::CYAN),
         ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions() -> Vec<Vec4> {
+        vec![
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+            Vec4::new(0.0, 1.0, 0.0, 1.0),
+            Vec4::new(1.0, 0.0, 0.0, 1.0),
+            Vec4::new(2.0, 0.0, 0.0, 1.0),
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+        ]
+    }
+
+    fn triangles(corners: &[[i32; 3]]) -> Vec<Triangle> {
+        corners
+            .iter()
+            .map(|&[a, b, c]| Triangle::new(a, b, c, color::WHITE))
+            .collect()
+    }
+
+    #[test]
+    fn accepts_valid_triangles() {
+        // The same vertices wound the other way are the back of the triangle
+        let model = Model::try_new(positions(), triangles(&[[0, 1, 2], [0, 2, 1], [1, 3, 2]]));
+        assert_eq!(model.unwrap().triangles.len(), 3);
+    }
+
+    #[test]
+    fn index_out_of_range() {
+        for index in [5, -1] {
+            let error = Model::try_new(positions(), triangles(&[[0, 1, 2], [0, index, 2]]));
+            assert_eq!(
+                error.err(),
+                Some(ModelError::IndexOutOfRange {
+                    triangle: 1,
+                    index,
+                    vertex_count: 5,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn degenerate() {
+        // A shared vertex, two vertices on the same spot, corners in a line
+        // and a sliver too thin to count
+        let mut positions = positions();
+        positions.push(Vec4::new(1.0, 1e-8, 0.0, 1.0));
+        for corners in [[0, 0, 2], [0, 4, 1], [0, 2, 3], [0, 5, 3]] {
+            let error = Model::try_new(positions.clone(), triangles(&[corners]));
+            assert_eq!(
+                error.err(),
+                Some(ModelError::Degenerate {
+                    triangle: 0,
+                    vertices: corners,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn small_triangles_have_area() {
+        let positions = vec![
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+            Vec4::new(0.0, 1e-4, 0.0, 1.0),
+            Vec4::new(1e-4, 0.0, 0.0, 1.0),
+        ];
+        assert!(Model::try_new(positions, triangles(&[[0, 1, 2]])).is_ok());
+    }
+
+    #[test]
+    fn duplicate() {
+        // Rotating the corners keeps the winding
+        let error = Model::try_new(positions(), triangles(&[[0, 1, 2], [1, 2, 0]]));
+        assert_eq!(
+            error.err(),
+            Some(ModelError::Duplicate {
+                triangle: 1,
+                original: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn validate_reports_every_triangle() {
+        let errors = validate(
+            &positions(),
+            &triangles(&[[0, 1, 2], [0, 0, 1], [2, 0, 1], [7, 0, 1]]),
+        );
+        let triangles: Vec<usize> = errors.iter().map(ModelError::get_triangle).collect();
+        assert_eq!(triangles, [1, 2, 3]);
+    }
+
+    #[test]
+    fn lenient_skips_degenerate_and_duplicate() {
+        let (model, skipped) = Model::try_new_lenient(
+            positions(),
+            triangles(&[[0, 1, 2], [0, 0, 1], [1, 3, 2], [2, 0, 1]]),
+        )
+        .unwrap();
+        assert_eq!(skipped, [1, 3]);
+        let kept: Vec<[i32; 3]> = model.triangles.iter().map(|t| t.vertices).collect();
+        assert_eq!(kept, [[0, 1, 2], [1, 3, 2]]);
+        assert_eq!(model.warnings.len(), 1);
+    }
+
+    #[test]
+    fn lenient_without_problems_has_no_warnings() {
+        let (model, skipped) =
+            Model::try_new_lenient(positions(), triangles(&[[0, 1, 2]])).unwrap();
+        assert!(skipped.is_empty());
+        assert!(model.warnings.is_empty());
+    }
+
+    #[test]
+    fn lenient_still_rejects_missing_vertices() {
+        let error = Model::try_new_lenient(positions(), triangles(&[[0, 0, 1], [0, 1, 9]]));
+        assert!(matches!(
+            error,
+            Err(ModelError::IndexOutOfRange { triangle: 1, .. })
+        ));
+    }
+
+    /// Unit vectors closer than a small angle
+    fn same_direction(a: &Vec3, b: &Vec3) -> bool {
+        a.normalize().dot(&b.normalize()) > 1.0 - 1e-5
+    }
+
+    #[test]
+    fn sharp_cube_splits_into_faces() {
+        let mut flat = default_cube();
+        flat.compute_flat_normals();
+        let mut smooth = default_cube();
+        // Neighboring faces of a cube are 90 degrees apart
+        smooth.compute_smooth_normals(NormalWeighting::Area, 60f32.to_radians());
+
+        for model in [flat, smooth] {
+            assert_eq!(model.vertices.len(), 24);
+            for (triangle, face) in model.triangles.iter().zip(model.face_normals()) {
+                for &i in &triangle.vertices {
+                    let normal = model.vertices[i as usize].normal.unwrap();
+                    assert!(same_direction(&normal, &face), "{normal} != {face}");
+                    assert_eq!(normal.iter().filter(|&&value| value != 0.0).count(), 1);
+                    assert!((normal.norm() - 1.0).abs() < 1e-6);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn smooth_cube_points_along_diagonals() {
+        let mut model = default_cube();
+        // Angles even out the two triangles some faces have at a corner
+        model.compute_smooth_normals(NormalWeighting::Angle, 100f32.to_radians());
+        assert_eq!(model.vertices.len(), 8);
+        for vertex in &model.vertices {
+            let normal = vertex.normal.unwrap();
+            assert!(same_direction(&normal, &vertex.position.xyz()), "{normal}");
+            assert!((normal.norm() - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn weighting_changes_uneven_fans() {
+        // A long thin triangle on the xy plane and a small right angled one
+        // on the yz plane meet at the origin
+        let positions = vec![
+            Vec4::new(0.0, 0.0, 0.0, 1.0),
+            Vec4::new(10.0, 1.0, 0.0, 1.0),
+            Vec4::new(10.0, 0.0, 0.0, 1.0),
+            Vec4::new(0.0, 0.0, 1.0, 1.0),
+            Vec4::new(0.0, 1.0, 0.0, 1.0),
+        ];
+        let fan = Model::new(positions, triangles(&[[0, 1, 2], [0, 3, 4]]));
+        let normal_at_origin = |weighting| {
+            let mut model = Model::new(
+                fan.vertices.iter().map(|v| v.position).collect(),
+                fan.triangles.clone(),
+            );
+            model.compute_smooth_normals(weighting, std::f32::consts::PI);
+            model.vertices[0].normal.unwrap()
+        };
+
+        // The big face wins by area, the wide corner by angle
+        let area = normal_at_origin(NormalWeighting::Area);
+        let angle = normal_at_origin(NormalWeighting::Angle);
+        assert!(area.z.abs() > 5.0 * area.x.abs(), "{area}");
+        assert!(angle.x.abs() > 5.0 * angle.z.abs(), "{angle}");
+    }
+
+    #[test]
+    fn normal_transform_keeps_normals_perpendicular() {
+        let instance = Instance::new(
+            Rc::new(default_cube()),
+            &Vec4::new(3.0, 1.0, 0.5, 0.0),
+            &Vec4::new(0.3, 0.7, 0.1, 0.0),
+            &Vec4::new(1.0, 2.0, 10.0, 0.0),
+        );
+        let normal = Vec4::new(1.0, 1.0, 1.0, 0.0);
+        let tangent = Vec4::new(1.0, -1.0, 0.0, 0.0);
+
+        let tangent = instance.get_transform() * tangent;
+        let kept = instance.get_normal_transform() * normal;
+        assert!(kept.normalize().dot(&tangent.normalize()).abs() < 1e-5);
+        // Moving the normal like a position would tilt it
+        let moved = instance.get_transform() * normal;
+        assert!(moved.normalize().dot(&tangent.normalize()).abs() > 0.1);
     }
 }
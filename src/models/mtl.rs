@@ -0,0 +1,201 @@
+//! Wavefront MTL material libraries, as referenced by OBJ files.
+//!
+//! Only what our renderer can use is read: `Kd`, `Ks`, `Ns`, `d` (or `Tr`)
+//! and `map_Kd`. Other records are skipped since exporters add plenty of
+//! their own.
+
+use crate::color::Color;
+use crate::image;
+use crate::image::Image;
+use crate::models::material::Material;
+use crate::models::obj::ObjError;
+use crate::rendering::texture::FilterMode;
+use crate::text::numbers;
+use nalgebra_glm::Vec3;
+use std::path::Path;
+use std::rc::Rc;
+
+/// Reads and parses a material library, loading textures relative to it
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Material>, ObjError> {
+    let path = path.as_ref();
+    parse(
+        &std::fs::read_to_string(path)?,
+        path.parent().unwrap_or(Path::new("")),
+    )
+}
+
+/// Parses a material library, loading textures relative to `base`.
+///
+/// Textures are tinted by the diffuse color of their material, wherever it
+/// appears in the material.
+pub fn parse(text: &str, base: &Path) -> Result<Vec<Material>, ObjError> {
+    let mut materials: Vec<Material> = vec![];
+    // Texture of each material, tinted once all of its records are read
+    let mut maps: Vec<Option<Image>> = vec![];
+
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: String| ObjError::Parse {
+            line: i + 1,
+            message,
+        };
+        let line = line.split('#').next().unwrap();
+        let mut words = line.split_whitespace();
+        let Some(record) = words.next() else {
+            continue;
+        };
+        let words: Vec<&str> = words.collect();
+
+        if record == "newmtl" {
+            materials.push(Material::new(&words.join(" ")));
+            maps.push(None);
+            continue;
+        }
+        let Some(material) = materials.last_mut() else {
+            return Err(error(format!("{record} before the first newmtl")));
+        };
+        match record {
+            "Kd" => material.diffuse = color(&words).map_err(error)?,
+            "Ks" => material.specular = color(&words).map_err(error)?,
+            "Ns" => material.shininess = numbers::<1>(&words).map_err(error)?[0],
+            "d" => material.opacity = numbers::<1>(&words).map_err(error)?[0],
+            "Tr" => material.opacity = 1.0 - numbers::<1>(&words).map_err(error)?[0],
+            "map_Kd" => {
+                // Options such as `-s 1 1 1` come first, the file name last
+                let Some(name) = words.last() else {
+                    return Err(error("map_Kd needs a file name".to_string()));
+                };
+                let image = image::load(base.join(name))
+                    .map_err(|image_error| error(format!("{name}: {image_error}")))?;
+                *maps.last_mut().unwrap() = Some(image);
+            }
+            _ => {}
+        }
+    }
+
+    for (material, map) in materials.iter_mut().zip(maps) {
+        material.diffuse_map = map.map(|image| {
+            let texture = image
+                .with_tint(material.diffuse)
+                .into_texture()
+                .with_mipmaps()
+                .with_filter(FilterMode::Trilinear);
+            Rc::new(texture)
+        });
+    }
+
+    Ok(materials)
+}
+
+/// Parses `r g b` between 0 and 1, or a single value for gray
+fn color(words: &[&str]) -> Result<Color, String> {
+    let rgb = match words.len() {
+        1 => Vec3::repeat(numbers::<1>(words)?[0]),
+        _ => Vec3::from(numbers::<3>(words)?),
+    };
+    Ok(Color::from(rgb))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color;
+    use crate::image::ppm;
+    use crate::models::obj;
+
+    /// A directory of its own for files the test needs
+    fn scratch_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("rasterizer-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn reads_colors_and_shininess() {
+        let text = "
+            # comment
+            newmtl plain
+            Kd 0.5
+            newmtl shiny
+            Kd 1 0 0
+            Ks 0 0 1
+            Ns 32
+            d 0.5
+            illum 2
+            newmtl clear
+            Tr 0.25
+        ";
+        let materials = parse(text, Path::new("")).unwrap();
+        assert_eq!(materials[0].name, "plain");
+        assert_eq!(materials[0].diffuse, Color::new(128, 128, 128));
+        assert_eq!(materials[0].get_specular_exponent(), None);
+        assert_eq!(materials[1].diffuse, color::RED);
+        assert_eq!(materials[1].specular, color::BLUE);
+        assert_eq!(materials[1].get_specular_exponent(), Some(32.0));
+        assert_eq!(materials[1].opacity, 0.5);
+        assert_eq!(materials[0].opacity, 1.0);
+        assert_eq!(materials[2].opacity, 0.75);
+    }
+
+    #[test]
+    fn records_need_a_material() {
+        assert!(matches!(
+            parse("\nKd 1 1 1", Path::new("")),
+            Err(ObjError::Parse { line: 2, .. })
+        ));
+        assert!(matches!(
+            parse("newmtl a\nKs 1 x 1", Path::new("")),
+            Err(ObjError::Parse { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn diffuse_color_tints_the_map() {
+        let dir = scratch_dir("mtl");
+        let image = Image {
+            width: 1,
+            height: 1,
+            pixels: vec![Color::new(200, 100, 50)],
+        };
+        std::fs::write(dir.join("pixel.ppm"), ppm::encode(&image)).unwrap();
+
+        // Kd may come after map_Kd
+        let text = "newmtl a\nmap_Kd -s 1 1 1 pixel.ppm\nKd 0.5 1 0\nnewmtl b\nKd 0.5 1 0";
+        let materials = parse(text, &dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let texture = materials[0].diffuse_map.as_ref().unwrap();
+        assert_eq!(texture.get_pixel(0, 0), Color::new(100, 100, 0));
+        assert!(materials[1].diffuse_map.is_none());
+    }
+
+    #[test]
+    fn obj_warns_about_missing_materials() {
+        let dir = scratch_dir("obj-mtl");
+        std::fs::write(
+            dir.join("found.mtl"),
+            "newmtl red\nKd 1 0 0\nKs 1 1 1\nNs 8",
+        )
+        .unwrap();
+        let text = "
+            mtllib missing.mtl found.mtl
+            v 0 0 0
+            v 1 0 0
+            v 0 1 0
+            usemtl red
+            f 1 2 3
+            usemtl blue
+            f 1 3 2
+        ";
+        let model = obj::parse(text, &dir).unwrap().model;
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(model.warnings.len(), 2);
+        assert!(model.warnings[0].contains("missing.mtl"));
+        assert!(model.warnings[1].contains("blue"));
+        let [red, default] = [&model.triangles[0], &model.triangles[1]];
+        assert_eq!(red.color, color::RED);
+        assert_eq!(red.specular, Some(8.0));
+        assert_eq!(red.specular_color, Some(color::WHITE));
+        assert_eq!(default.color, color::WHITE);
+        assert!(default.material.is_none());
+    }
+}
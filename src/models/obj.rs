@@ -0,0 +1,387 @@
+//! Wavefront OBJ models.
+//!
+//! OBJ is right-handed with counter-clockwise front faces while we are
+//! left-handed with clockwise ones, so z is mirrored and faces are reversed
+//! while loading. Texture coordinates are flipped vertically to put their
+//! origin at the top left.
+//!
+//! Material libraries named by `mtllib` are loaded relative to the OBJ file
+//! and `usemtl` applies their materials to the faces that follow. Libraries
+//! that fail to load and materials they don't define are reported in
+//! [`Model::warnings`], and the faces fall back to the default material.
+
+use crate::color;
+use crate::models::material::Material;
+use crate::models::model::Model;
+use crate::models::mtl;
+use crate::models::triangle::Triangle;
+use crate::text::numbers;
+use nalgebra_glm::{Vec2, Vec3, Vec4};
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::rc::Rc;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(std::io::Error),
+    /// A record we could not make sense of, on a 1-based line number
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(error) => write!(f, "{error}"),
+            ObjError::Parse { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+impl From<std::io::Error> for ObjError {
+    fn from(error: std::io::Error) -> Self {
+        ObjError::Io(error)
+    }
+}
+
+/// Triangles that were declared under the same `o` or `g` name
+pub struct Group {
+    pub name: String,
+    pub triangles: Range<usize>,
+}
+
+/// A loaded OBJ file
+pub struct Obj {
+    pub model: Model,
+    /// Named groups in the order they appear. Triangles before the first
+    /// `o` or `g` record are not part of any group.
+    pub groups: Vec<Group>,
+}
+
+/// Reads and parses an OBJ file along with its material libraries
+pub fn load(path: impl AsRef<Path>) -> Result<Obj, ObjError> {
+    let path = path.as_ref();
+    parse(
+        &std::fs::read_to_string(path)?,
+        path.parent().unwrap_or(Path::new("")),
+    )
+}
+
+/// Parses the contents of an OBJ file, loading material libraries relative
+/// to `base`.
+///
+/// Polygons are triangulated as fans. Normals and texture coordinates are
+/// only kept when every face provides them. Triangles without area and
+/// repeated ones are left out, see [`Model::try_new_lenient`]. Records we
+/// don't know are skipped.
+pub fn parse(text: &str, base: &Path) -> Result<Obj, ObjError> {
+    let mut positions: Vec<Vec4> = vec![];
+    let mut uvs: Vec<Vec2> = vec![];
+    let mut normals: Vec<Vec3> = vec![];
+
+    // OBJ indexes positions, uvs and normals separately, so every distinct
+    // combination becomes a vertex of its own
+    let mut corners: HashMap<(usize, Option<usize>, Option<usize>), i32> = HashMap::new();
+    let mut vertices: Vec<(usize, Option<usize>, Option<usize>)> = vec![];
+    let mut triangles: Vec<Triangle> = vec![];
+    // The line every triangle comes from, to point validation errors at
+    let mut triangle_lines: Vec<usize> = vec![];
+    let mut groups: Vec<Group> = vec![];
+    let mut materials: HashMap<String, Rc<Material>> = HashMap::new();
+    let mut material: Option<Rc<Material>> = None;
+    let mut warnings: Vec<String> = vec![];
+
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: String| ObjError::Parse {
+            line: i + 1,
+            message,
+        };
+        let line = line.split('#').next().unwrap();
+        let mut words = line.split_whitespace();
+        let Some(record) = words.next() else {
+            continue;
+        };
+        let words: Vec<&str> = words.collect();
+
+        match record {
+            "v" => {
+                // An optional w and vertex colors may follow, which we ignore
+                let [x, y, z] = numbers::<3>(&words).map_err(error)?;
+                positions.push(Vec4::new(x, y, -z, 1.0));
+            }
+            "vt" => {
+                // v and w are optional
+                let [u] = numbers::<1>(&words).map_err(error)?;
+                let v = match words.get(1) {
+                    Some(_) => numbers::<2>(&words).map_err(error)?[1],
+                    None => 0.0,
+                };
+                uvs.push(Vec2::new(u, 1.0 - v));
+            }
+            "vn" => {
+                let [x, y, z] = numbers::<3>(&words).map_err(error)?;
+                normals.push(Vec3::new(x, y, -z));
+            }
+            "f" => {
+                if words.len() < 3 {
+                    return Err(error(format!(
+                        "face needs at least 3 vertices, found {}",
+                        words.len()
+                    )));
+                }
+                let mut face = Vec::with_capacity(words.len());
+                for word in &words {
+                    let corner = parse_corner(word, positions.len(), uvs.len(), normals.len())
+                        .map_err(error)?;
+                    let index = *corners.entry(corner).or_insert_with(|| {
+                        vertices.push(corner);
+                        (vertices.len() - 1) as i32
+                    });
+                    face.push(index);
+                }
+                for j in 1..face.len() - 1 {
+                    let triangle = Triangle::new(face[0], face[j + 1], face[j], color::WHITE);
+                    triangles.push(match &material {
+                        Some(material) => triangle.with_material(Rc::clone(material)),
+                        None => triangle,
+                    });
+                    triangle_lines.push(i + 1);
+                }
+            }
+            "o" | "g" => {
+                let name = words.join(" ");
+                if let Some(group) = groups.last_mut() {
+                    group.triangles.end = triangles.len();
+                }
+                groups.push(Group {
+                    name,
+                    triangles: triangles.len()..triangles.len(),
+                });
+            }
+            "mtllib" => {
+                for name in &words {
+                    match mtl::load(base.join(name)) {
+                        Ok(library) => {
+                            for loaded in library {
+                                materials.insert(loaded.name.clone(), Rc::new(loaded));
+                            }
+                        }
+                        Err(mtl_error) => {
+                            warnings.push(format!("line {}: {name}: {mtl_error}", i + 1))
+                        }
+                    }
+                }
+            }
+            "usemtl" => {
+                let name = words.join(" ");
+                material = materials.get(&name).cloned();
+                if material.is_none() {
+                    warnings.push(format!(
+                        "line {}: unknown material {name}, using the default",
+                        i + 1
+                    ));
+                }
+            }
+            // Smoothing groups, lines, points, free-form curves and surfaces,
+            // and render attributes don't affect the mesh
+            _ => {}
+        }
+    }
+    if let Some(group) = groups.last_mut() {
+        group.triangles.end = triangles.len();
+    }
+
+    let (mut model, skipped) = Model::try_new_lenient(
+        vertices.iter().map(|&(v, ..)| positions[v]).collect(),
+        triangles,
+    )
+    .map_err(|error| ObjError::Parse {
+        line: triangle_lines[error.get_triangle()],
+        message: error.to_string(),
+    })?;
+    model.warnings.append(&mut warnings);
+    // Groups shrink by the triangles left out of them
+    let shift = |index: usize| index - skipped.partition_point(|&i| i < index);
+    for group in &mut groups {
+        group.triangles = shift(group.triangles.start)..shift(group.triangles.end);
+    }
+    if !vertices.is_empty() && vertices.iter().all(|(_, vt, _)| vt.is_some()) {
+        model = model.with_uvs(vertices.iter().map(|(_, vt, _)| uvs[vt.unwrap()]).collect());
+    }
+    if !vertices.is_empty() && vertices.iter().all(|(.., vn)| vn.is_some()) {
+        model = model.with_normals(
+            vertices
+                .iter()
+                .map(|(.., vn)| {
+                    normals[vn.unwrap()]
+                        .try_normalize(f32::EPSILON)
+                        .unwrap_or_default()
+                })
+                .collect(),
+        );
+    }
+
+    Ok(Obj { model, groups })
+}
+
+/// Parses a face corner, `v`, `v/vt`, `v//vn` or `v/vt/vn`, into 0-based indices
+fn parse_corner(
+    word: &str,
+    positions: usize,
+    uvs: usize,
+    normals: usize,
+) -> Result<(usize, Option<usize>, Option<usize>), String> {
+    let mut parts = word.split('/');
+    let v = resolve_index(parts.next().unwrap(), positions, "vertex")?;
+    let vt = match parts.next() {
+        None | Some("") => None,
+        Some(part) => Some(resolve_index(part, uvs, "texture coordinate")?),
+    };
+    let vn = match parts.next() {
+        None | Some("") => None,
+        Some(part) => Some(resolve_index(part, normals, "normal")?),
+    };
+    if parts.next().is_some() {
+        return Err(format!("too many indices in {word}"));
+    }
+
+    Ok((v, vt, vn))
+}
+
+/// Turns a 1-based index, or a negative one counting back from the latest
+/// element, into a 0-based index
+fn resolve_index(word: &str, len: usize, kind: &str) -> Result<usize, String> {
+    let index: i64 = word
+        .parse()
+        .map_err(|_| format!("expected a {kind} index, found {word}"))?;
+    let resolved = match index {
+        1.. => index - 1,
+        ..=-1 => len as i64 + index,
+        0 => return Err(format!("{kind} index 0 is not allowed, indices start at 1")),
+    };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(format!(
+            "{kind} index {index} is out of range, {len} defined so far"
+        ));
+    }
+
+    Ok(resolved as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Obj, ObjError> {
+        super::parse(text, Path::new(""))
+    }
+
+    fn corners(model: &Model) -> Vec<[i32; 3]> {
+        model.triangles.iter().map(|t| t.vertices).collect()
+    }
+
+    const SQUARE: &str = "
+        v 0 0 1
+        v 1 0 1
+        v 1 1 1
+        v 0 1 1
+        vt 0 0
+        vt 1 0
+        vt 1 1
+        vt 0 1
+        f 1/1 2/2 3/3 4/4
+    ";
+
+    #[test]
+    fn mirrors_z_and_reverses_faces() {
+        let model = parse(SQUARE).unwrap().model;
+        assert_eq!(model.vertices[2].position, Vec4::new(1.0, 1.0, -1.0, 1.0));
+        // Fans out from the first corner, wound the other way
+        assert_eq!(corners(&model), [[0, 2, 1], [0, 3, 2]]);
+        // Faces counter-clockwise towards +z in the file end up facing -z
+        assert!(model.face_normals().iter().all(|normal| normal.z < 0.0));
+        assert_eq!(model.vertices[1].uv, Some(Vec2::new(1.0, 1.0)));
+    }
+
+    #[test]
+    fn negative_indices_count_back() {
+        let model = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1")
+            .unwrap()
+            .model;
+        assert_eq!(corners(&model), [[0, 2, 1]]);
+    }
+
+    #[test]
+    fn shares_vertices_between_faces() {
+        let model = parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nf 1 2 3\nf 2 4 3")
+            .unwrap()
+            .model;
+        assert_eq!(model.vertices.len(), 4);
+    }
+
+    #[test]
+    fn skips_unknown_records() {
+        let text = format!("{SQUARE}\ns 1\nl 1 2\ncstype bezier\nwhatever 1 2 3");
+        assert_eq!(parse(&text).unwrap().model.triangles.len(), 2);
+    }
+
+    #[test]
+    fn keeps_normals_only_when_every_corner_has_one() {
+        let text = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 2\nvn 0 0 0\n";
+        let model = parse(&format!("{text}f 1//1 2//1 3//2")).unwrap().model;
+        assert_eq!(model.vertices[0].normal, Some(Vec3::new(0.0, 0.0, -1.0)));
+        // A zero normal stays zero instead of turning into NaN
+        assert_eq!(model.vertices[2].normal, Some(Vec3::zeros()));
+
+        let model = parse(&format!("{text}f 1//1 2 3")).unwrap().model;
+        assert!(model.vertices.iter().all(|vertex| vertex.normal.is_none()));
+    }
+
+    #[test]
+    fn groups_skip_left_out_triangles() {
+        let text = "
+            v 0 0 0
+            v 1 0 0
+            v 0 1 0
+            f 1 2 3
+            g first
+            f 1 1 2
+            f 1 2 3
+            o second
+            f 3 1 2
+        ";
+        let obj = parse(text).unwrap();
+        assert_eq!(obj.model.triangles.len(), 1);
+        assert_eq!(obj.model.warnings.len(), 1);
+        let groups: Vec<(&str, Range<usize>)> = obj
+            .groups
+            .iter()
+            .map(|group| (group.name.as_str(), group.triangles.clone()))
+            .collect();
+        assert_eq!(groups, [("first", 1..1), ("second", 1..1)]);
+    }
+
+    #[test]
+    fn errors_point_at_the_line() {
+        for (text, line) in [
+            ("v 0 0\n", 1),
+            ("v 0 0 0\nv 1 0 0\nf 1 2\n", 3),
+            ("v 0 0 0\n\nf 1 2 4\n", 3),
+            ("v 0 0 0\nf 0 1 1\n", 2),
+            ("v 0 0 0\nf 1/1 1 1\n", 2),
+            ("v 0 0 0\nf 1/x 1 1\n", 2),
+            ("v 0 0 0\nf 1//1/1 1 1\n", 2),
+        ] {
+            match parse(text) {
+                Err(ObjError::Parse { line: found, .. }) => assert_eq!(found, line, "{text}"),
+                _ => panic!("{text} should not parse"),
+            }
+        }
+    }
+}
@@ -0,0 +1,604 @@
+//! PLY meshes in ASCII and binary of either byte order.
+//!
+//! Vertex positions, normals and colors are read along with the faces.
+//! Everything else is skipped, including properties and elements we don't
+//! know. Like OBJ, PLY is right-handed with counter-clockwise front faces, so
+//! z is mirrored and faces are reversed while loading.
+
+use crate::color;
+use crate::color::Color;
+use crate::models::model::Model;
+use crate::models::triangle::Triangle;
+use nalgebra_glm::{Vec3, Vec4};
+use std::fmt;
+use std::path::Path;
+use std::str::{Lines, SplitWhitespace};
+
+#[derive(Debug)]
+pub enum PlyError {
+    Io(std::io::Error),
+    /// Header or ASCII data we could not make sense of, on a 1-based line number
+    Parse {
+        line: usize,
+        message: String,
+    },
+    /// Binary data that breaks the format, at a byte offset
+    Malformed {
+        offset: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlyError::Io(error) => write!(f, "{error}"),
+            PlyError::Parse { line, message } => write!(f, "line {line}: {message}"),
+            PlyError::Malformed { offset, reason } => {
+                write!(f, "malformed PLY at byte {offset}: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PlyError {}
+
+impl From<std::io::Error> for PlyError {
+    fn from(error: std::io::Error) -> Self {
+        PlyError::Io(error)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Format {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+/// Type of a single value
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return None,
+        })
+    }
+
+    fn len(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    fn is_float(self) -> bool {
+        matches!(self, Scalar::F32 | Scalar::F64)
+    }
+
+    /// Decodes a value from exactly [`Scalar::len`] bytes
+    fn decode(self, bytes: &[u8], format: Format) -> f64 {
+        macro_rules! read {
+            ($type:ty) => {{
+                let bytes = bytes.try_into().unwrap();
+                match format {
+                    Format::BigEndian => <$type>::from_be_bytes(bytes) as f64,
+                    _ => <$type>::from_le_bytes(bytes) as f64,
+                }
+            }};
+        }
+        match self {
+            Scalar::I8 => read!(i8),
+            Scalar::U8 => read!(u8),
+            Scalar::I16 => read!(i16),
+            Scalar::U16 => read!(u16),
+            Scalar::I32 => read!(i32),
+            Scalar::U32 => read!(u32),
+            Scalar::F32 => read!(f32),
+            Scalar::F64 => read!(f64),
+        }
+    }
+}
+
+enum Property {
+    Scalar(Scalar),
+    /// A count followed by that many items
+    List {
+        count: Scalar,
+        item: Scalar,
+    },
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<(String, Property)>,
+}
+
+/// Where the values of the body come from
+enum Values<'a> {
+    Ascii {
+        lines: Lines<'a>,
+        words: SplitWhitespace<'a>,
+        line: usize,
+    },
+    Binary {
+        bytes: &'a [u8],
+        offset: usize,
+        format: Format,
+    },
+}
+
+impl Values<'_> {
+    fn next(&mut self, scalar: Scalar) -> Result<f64, PlyError> {
+        match self {
+            Values::Ascii { lines, words, line } => {
+                let word = loop {
+                    if let Some(word) = words.next() {
+                        break word;
+                    }
+                    match lines.next() {
+                        Some(next) => {
+                            *words = next.split_whitespace();
+                            *line += 1;
+                        }
+                        None => return Err(self.error("unexpected end of file")),
+                    }
+                };
+                let value = match scalar.is_float() {
+                    true => word.parse::<f64>().ok(),
+                    false => word.parse::<i64>().ok().map(|value| value as f64),
+                };
+                value.ok_or_else(|| self.error(&format!("expected a number, found {word}")))
+            }
+            Values::Binary {
+                bytes,
+                offset,
+                format,
+            } => {
+                let Some(data) = bytes.get(*offset..*offset + scalar.len()) else {
+                    return Err(self.error("unexpected end of file"));
+                };
+                *offset += scalar.len();
+                Ok(scalar.decode(data, *format))
+            }
+        }
+    }
+
+    /// The line or byte offset we have read up to
+    fn position(&self) -> usize {
+        match self {
+            Values::Ascii { line, .. } => *line,
+            Values::Binary { offset, .. } => *offset,
+        }
+    }
+
+    fn error(&self, message: &str) -> PlyError {
+        self.error_at(self.position(), message)
+    }
+
+    /// An error at an earlier [`Values::position`]
+    fn error_at(&self, position: usize, message: &str) -> PlyError {
+        match self {
+            Values::Ascii { .. } => PlyError::Parse {
+                line: position,
+                message: message.to_string(),
+            },
+            Values::Binary { .. } => PlyError::Malformed {
+                offset: position,
+                reason: message.to_string(),
+            },
+        }
+    }
+}
+
+/// Reads and decodes a PLY file
+pub fn load(path: impl AsRef<Path>) -> Result<Model, PlyError> {
+    decode(&std::fs::read(path)?)
+}
+
+/// Decodes a PLY file
+pub fn decode(bytes: &[u8]) -> Result<Model, PlyError> {
+    let (format, elements, header_len, header_lines) = parse_header(bytes)?;
+    let mut values = match format {
+        Format::Ascii => {
+            let text =
+                std::str::from_utf8(&bytes[header_len..]).map_err(|error| PlyError::Malformed {
+                    offset: header_len + error.valid_up_to(),
+                    reason: "ASCII PLY is not valid UTF-8".to_string(),
+                })?;
+            Values::Ascii {
+                lines: text.lines(),
+                words: "".split_whitespace(),
+                line: header_lines,
+            }
+        }
+        _ => Values::Binary {
+            bytes,
+            offset: header_len,
+            format,
+        },
+    };
+
+    let mut vertices: Vec<Vec4> = vec![];
+    let mut normals: Vec<Vec3> = vec![];
+    let mut colors: Vec<Color> = vec![];
+    let mut triangles: Vec<Triangle> = vec![];
+    // Where every triangle was read, to point validation errors at
+    let mut triangle_positions: Vec<usize> = vec![];
+    let mut has_normals = false;
+    let mut has_colors = false;
+    let vertex_count = elements
+        .iter()
+        .find(|element| element.name == "vertex")
+        .map_or(0, |element| element.count);
+
+    for element in &elements {
+        let position = |name: &str| element.properties.iter().position(|(n, _)| n == name);
+        let fields = ["x", "y", "z", "nx", "ny", "nz", "red", "green", "blue"].map(position);
+        let faces = position("vertex_indices").or_else(|| position("vertex_index"));
+        if element.name == "vertex" {
+            if fields[..3].iter().any(Option::is_none) {
+                return Err(values.error("vertex element needs x, y and z properties"));
+            }
+            has_normals = fields[3..6].iter().all(Option::is_some);
+            has_colors = fields[6..].iter().all(Option::is_some);
+        }
+
+        for _ in 0..element.count {
+            let mut scalars = [0.0f64; 9];
+            let mut indices: Vec<i64> = vec![];
+            for (k, (_, property)) in element.properties.iter().enumerate() {
+                match *property {
+                    Property::Scalar(scalar) => {
+                        let mut value = values.next(scalar)?;
+                        if let Some(field) = fields.iter().position(|&field| field == Some(k)) {
+                            // Integer colors are 0 to 255, floating point ones 0 to 1
+                            if field >= 6 && scalar.is_float() {
+                                value *= 255.0;
+                            }
+                            scalars[field] = value;
+                        }
+                    }
+                    Property::List { count, item } => {
+                        let len = values.next(count)?;
+                        if len < 0.0 {
+                            return Err(values.error(&format!("negative list length {len}")));
+                        }
+                        for _ in 0..len as usize {
+                            let value = values.next(item)?;
+                            if faces == Some(k) {
+                                indices.push(value as i64);
+                            }
+                        }
+                    }
+                }
+            }
+
+            match element.name.as_str() {
+                "vertex" => {
+                    let [x, y, z, nx, ny, nz, r, g, b] = scalars.map(|value| value as f32);
+                    vertices.push(Vec4::new(x, y, -z, 1.0));
+                    if has_normals {
+                        normals.push(
+                            Vec3::new(nx, ny, -nz)
+                                .try_normalize(f32::EPSILON)
+                                .unwrap_or_default(),
+                        );
+                    }
+                    if has_colors {
+                        let channel = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+                        colors.push(Color::new(channel(r), channel(g), channel(b)));
+                    }
+                }
+                "face" => {
+                    if indices.len() < 3 {
+                        return Err(values.error(&format!(
+                            "face needs at least 3 vertices, found {}",
+                            indices.len()
+                        )));
+                    }
+                    if let Some(index) = indices
+                        .iter()
+                        .find(|&&index| index < 0 || index as usize >= vertex_count)
+                    {
+                        return Err(values
+                            .error(&format!("face refers to vertex {index} of {vertex_count}")));
+                    }
+                    let indices: Vec<i32> = indices.iter().map(|&index| index as i32).collect();
+                    for j in 1..indices.len() - 1 {
+                        triangles.push(Triangle::new(
+                            indices[0],
+                            indices[j + 1],
+                            indices[j],
+                            color::WHITE,
+                        ));
+                        triangle_positions.push(values.position());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let (mut model, _) = Model::try_new_lenient(vertices, triangles).map_err(|error| {
+        values.error_at(triangle_positions[error.get_triangle()], &error.to_string())
+    })?;
+    if has_normals {
+        model = model.with_normals(normals);
+    }
+    if has_colors {
+        model = model.with_colors(colors);
+    }
+    Ok(model)
+}
+
+/// Reads the header, returning the format, the elements, the size of the
+/// header in bytes and in lines
+fn parse_header(bytes: &[u8]) -> Result<(Format, Vec<Element>, usize, usize), PlyError> {
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    let mut offset = 0;
+
+    for line_number in 1.. {
+        let error = |message: String| PlyError::Parse {
+            line: line_number,
+            message,
+        };
+        let Some(end) = bytes[offset..].iter().position(|&byte| byte == b'\n') else {
+            return Err(error("header has no end_header".to_string()));
+        };
+        let line = std::str::from_utf8(&bytes[offset..offset + end])
+            .map_err(|_| error("header is not text".to_string()))?;
+        offset += end + 1;
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        if line_number == 1 {
+            if words != ["ply"] {
+                return Err(error("expected ply magic number".to_string()));
+            }
+            continue;
+        }
+        match words.as_slice() {
+            ["format", name, version] => {
+                if *version != "1.0" {
+                    return Err(error(format!("unsupported version {version}")));
+                }
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::LittleEndian,
+                    "binary_big_endian" => Format::BigEndian,
+                    _ => return Err(error(format!("unknown format {name}"))),
+                });
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| error(format!("invalid element count {count}")))?,
+                properties: vec![],
+            }),
+            ["property", rest @ ..] => {
+                let Some(element) = elements.last_mut() else {
+                    return Err(error("property before the first element".to_string()));
+                };
+                let scalar = |name: &str| {
+                    Scalar::from_name(name).ok_or_else(|| error(format!("unknown type {name}")))
+                };
+                let (name, property) = match rest {
+                    ["list", count, item, name] => (
+                        name,
+                        Property::List {
+                            count: scalar(count)?,
+                            item: scalar(item)?,
+                        },
+                    ),
+                    [kind, name] => (name, Property::Scalar(scalar(kind)?)),
+                    _ => return Err(error(format!("malformed property {line}"))),
+                };
+                element.properties.push((name.to_string(), property));
+            }
+            ["end_header"] => {
+                let Some(format) = format else {
+                    return Err(error("header has no format".to_string()));
+                };
+                return Ok((format, elements, offset, line_number));
+            }
+            _ => return Err(error(format!("unexpected header line {line}"))),
+        }
+    }
+
+    unreachable!()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "ply
+format {format} 1.0
+comment a triangle and a quad
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 2
+property list uchar int vertex_indices
+end_header
+";
+
+    fn header(format: &str) -> String {
+        HEADER.replace("{format}", format)
+    }
+
+    const ASCII_BODY: &str = "0 0 1 255 0 0
+1 0 1 0 255 0
+1 1 1 0 0 255
+0 1 1 10 20 30
+3 0 1 2
+4 0 1 2 3
+";
+
+    /// The same body as [`ASCII_BODY`] in binary
+    fn binary_body(big_endian: bool) -> Vec<u8> {
+        let mut bytes = vec![];
+        let positions = [
+            [0.0f32, 0.0, 1.0],
+            [1.0, 0.0, 1.0],
+            [1.0, 1.0, 1.0],
+            [0.0, 1.0, 1.0],
+        ];
+        let colors = [[255, 0, 0], [0, 255, 0], [0, 0, 255], [10, 20, 30]];
+        for (position, color) in positions.iter().zip(colors) {
+            for value in position {
+                bytes.extend(match big_endian {
+                    true => value.to_be_bytes(),
+                    false => value.to_le_bytes(),
+                });
+            }
+            bytes.extend(color);
+        }
+        for face in [&[0i32, 1, 2][..], &[0, 1, 2, 3]] {
+            bytes.push(face.len() as u8);
+            for &index in face {
+                bytes.extend(match big_endian {
+                    true => index.to_be_bytes(),
+                    false => index.to_le_bytes(),
+                });
+            }
+        }
+        bytes
+    }
+
+    fn check_model(model: &Model) {
+        assert_eq!(model.vertices.len(), 4);
+        assert_eq!(model.vertices[2].position, Vec4::new(1.0, 1.0, -1.0, 1.0));
+        assert_eq!(model.vertices[3].color, Some(Color::new(10, 20, 30)));
+        // The quad fans into a triangle repeating the first one, which is left out
+        let corners: Vec<[i32; 3]> = model.triangles.iter().map(|t| t.vertices).collect();
+        assert_eq!(corners, [[0, 2, 1], [0, 3, 2]]);
+        assert_eq!(model.warnings.len(), 1);
+        assert!(model.face_normals().iter().all(|normal| normal.z < 0.0));
+    }
+
+    #[test]
+    fn decodes_ascii() {
+        check_model(&decode(format!("{}{ASCII_BODY}", header("ascii")).as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn decodes_binary_of_either_byte_order() {
+        for (name, big_endian) in [("binary_little_endian", false), ("binary_big_endian", true)] {
+            let mut bytes = header(name).into_bytes();
+            bytes.extend(binary_body(big_endian));
+            check_model(&decode(&bytes).unwrap());
+        }
+    }
+
+    #[test]
+    fn skips_unknown_properties_and_elements() {
+        let text = "ply
+format ascii 1.0
+element vertex 3
+property float x
+property float confidence
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property float red
+property float green
+property float blue
+element face 1
+property list uchar uint vertex_index
+property list uchar float texcoord
+element material 1
+property uchar red
+end_header
+0 9 0 0 0 0 2 1 0 0
+1 9 0 0 0 0 2 0 1 0
+0 9 1 0 0 0 0 0 0 0.5
+3 0 1 2 2 0.5 0.5
+255
+";
+        let model = decode(text.as_bytes()).unwrap();
+        assert_eq!(model.triangles.len(), 1);
+        assert_eq!(model.vertices[0].normal, Some(Vec3::new(0.0, 0.0, -1.0)));
+        // A zero normal stays zero instead of turning into NaN
+        assert_eq!(model.vertices[2].normal, Some(Vec3::zeros()));
+        // Floating point colors go from 0 to 1
+        assert_eq!(model.vertices[1].color, Some(color::GREEN));
+        assert_eq!(model.vertices[2].color, Some(Color::new(0, 0, 128)));
+    }
+
+    #[test]
+    fn malformed_header_is_an_error() {
+        for (text, line) in [
+            ("plx\n", 1),
+            ("ply\nformat ascii 2.0\nend_header\n", 2),
+            ("ply\nformat ascii 1.0\nelement vertex x\nend_header\n", 3),
+            ("ply\nformat ascii 1.0\n", 3),
+        ] {
+            match decode(text.as_bytes()) {
+                Err(PlyError::Parse { line: found, .. }) => assert_eq!(found, line, "{text}"),
+                _ => panic!("{text} should not decode"),
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_ascii_is_an_error() {
+        let ascii = header("ascii");
+        let header_lines = ascii.lines().count();
+        for (body, line) in [
+            (ASCII_BODY.replace("3 0 1 2\n", "3 0 1 7\n"), 5),
+            (ASCII_BODY.replace("3 0 1 2\n", "2 0 1\n"), 5),
+            (ASCII_BODY.replace("1 1 1 0", "1 x 1 0"), 3),
+            (ASCII_BODY.replace("4 0 1 2 3\n", "4 0 1 2\n"), 6),
+        ] {
+            match decode(format!("{ascii}{body}").as_bytes()) {
+                Err(PlyError::Parse { line: found, .. }) => {
+                    assert_eq!(found, header_lines + line, "{body}")
+                }
+                _ => panic!("{body} should not decode"),
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_binary_is_an_error() {
+        let mut bytes = header("binary_little_endian").into_bytes();
+        bytes.extend(binary_body(false));
+        bytes.pop();
+        assert!(matches!(
+            decode(&bytes),
+            Err(PlyError::Malformed { offset, .. }) if offset == bytes.len() - 3
+        ));
+    }
+}
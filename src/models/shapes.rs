@@ -0,0 +1,464 @@
+//! Procedurally generated meshes.
+//!
+//! Every shape is centered on the origin with +y up and comes with unit
+//! normals and texture coordinates. Surfaces that wrap around repeat the
+//! vertices along their seam so the texture coordinates can run from 0 to 1.
+
+use crate::color;
+use crate::models::model::Model;
+use crate::models::triangle::Triangle;
+use nalgebra_glm::{Vec2, Vec3, Vec4};
+use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
+
+/// Collects vertices and triangles of a shape under construction
+#[derive(Default)]
+struct Builder {
+    positions: Vec<Vec4>,
+    normals: Vec<Vec3>,
+    uvs: Vec<Vec2>,
+    triangles: Vec<Triangle>,
+}
+
+impl Builder {
+    fn vertex(&mut self, position: Vec3, normal: Vec3, uv: Vec2) -> i32 {
+        self.positions
+            .push(Vec4::new(position.x, position.y, position.z, 1.0));
+        self.normals.push(normal.normalize());
+        self.uvs.push(uv);
+        (self.positions.len() - 1) as i32
+    }
+
+    fn triangle(&mut self, a: i32, b: i32, c: i32) {
+        self.triangles.push(Triangle::new(a, b, c, color::WHITE));
+    }
+
+    /// Adds the triangle unless two of its corners sit on the same spot, as
+    /// happens where a grid closes up at a pole
+    fn triangle_with_area(&mut self, a: i32, b: i32, c: i32) {
+        let at = |i: i32| self.positions[i as usize];
+        if at(a) != at(b) && at(b) != at(c) && at(c) != at(a) {
+            self.triangle(a, b, c);
+        }
+    }
+
+    /// Stitches vertices laid out as `rows + 1` rows of `columns + 1`, starting
+    /// at `first`, into quads.
+    ///
+    /// Columns must run around the surface such that going one column over
+    /// and then one row down turns clockwise when seen from outside.
+    fn grid(&mut self, first: i32, rows: usize, columns: usize) {
+        let index = |row: usize, column: usize| first + (row * (columns + 1) + column) as i32;
+        for row in 0..rows {
+            for column in 0..columns {
+                let (a, b) = (index(row, column), index(row, column + 1));
+                let (c, d) = (index(row + 1, column + 1), index(row + 1, column));
+                self.triangle_with_area(a, b, c);
+                self.triangle_with_area(a, c, d);
+            }
+        }
+    }
+
+    fn build(self) -> Model {
+        Model::new(self.positions, self.triangles)
+            .with_normals(self.normals)
+            .with_uvs(self.uvs)
+    }
+}
+
+/// Point on the unit circle in the xz plane, counter-clockwise seen from above
+fn around(angle: f32) -> Vec3 {
+    Vec3::new(angle.cos(), 0.0, angle.sin())
+}
+
+/// Point on the unit sphere `polar` radians down from the top
+fn meridian(angle: f32, polar: f32) -> Vec3 {
+    // sin(π) comes out a hair below zero, which would keep the bottom pole
+    // from closing up into a single point
+    around(angle) * polar.sin().max(0.0) + Vec3::y() * polar.cos()
+}
+
+/// A sphere made of `rings` bands of `segments` quads, like lines of latitude
+/// and longitude.
+pub fn uv_sphere(radius: f32, segments: usize, rings: usize) -> Model {
+    assert!(
+        segments >= 3 && rings >= 2,
+        "sphere needs 3 segments and 2 rings"
+    );
+    let mut builder = Builder::default();
+    for ring in 0..=rings {
+        let v = ring as f32 / rings as f32;
+        let polar = v * PI;
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let normal = meridian(u * TAU, polar);
+            builder.vertex(normal * radius, normal, Vec2::new(u, v));
+        }
+    }
+    builder.grid(0, rings, segments);
+
+    builder.build()
+}
+
+/// A sphere made by repeatedly splitting the faces of an icosahedron into
+/// four, which spreads the triangles more evenly than [`uv_sphere`].
+pub fn icosphere(radius: f32, subdivisions: usize) -> Model {
+    let t = (1.0 + 5.0f32.sqrt()) / 2.0;
+    let mut points: Vec<Vec3> = [
+        (-1.0, t, 0.0),
+        (1.0, t, 0.0),
+        (-1.0, -t, 0.0),
+        (1.0, -t, 0.0),
+        (0.0, -1.0, t),
+        (0.0, 1.0, t),
+        (0.0, -1.0, -t),
+        (0.0, 1.0, -t),
+        (t, 0.0, -1.0),
+        (t, 0.0, 1.0),
+        (-t, 0.0, -1.0),
+        (-t, 0.0, 1.0),
+    ]
+    .iter()
+    .map(|&(x, y, z)| Vec3::new(x, y, z).normalize())
+    .collect();
+    let mut faces: Vec<[usize; 3]> = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+    // Wind every face clockwise seen from outside
+    for face in &mut faces {
+        let [a, b, c] = face.map(|i| points[i]);
+        if (b - a).cross(&(c - a)).dot(&(a + b + c)) < 0.0 {
+            face.swap(1, 2);
+        }
+    }
+
+    for _ in 0..subdivisions {
+        let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut midpoint = |a: usize, b: usize| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                points.push((points[a] + points[b]).normalize());
+                points.len() - 1
+            })
+        };
+        faces = faces
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    // Texture coordinates wrap around, so faces crossing the seam need
+    // vertices of their own. There u runs a little past 1, which repeating
+    // textures take in their stride. No vertex lands on a pole, where u would
+    // be undefined.
+    let mut builder = Builder::default();
+    let mut corners: HashMap<(usize, [u32; 2]), i32> = HashMap::new();
+    for face in faces {
+        let mut uvs = face.map(|i| {
+            let point = points[i];
+            let u = (point.z.atan2(point.x) / TAU).rem_euclid(1.0);
+            Vec2::new(u, point.y.clamp(-1.0, 1.0).acos() / PI)
+        });
+        let (min, max) = uvs.iter().fold((1.0f32, 0.0f32), |(min, max), uv| {
+            (min.min(uv.x), max.max(uv.x))
+        });
+        if max - min > 0.5 {
+            for uv in &mut uvs {
+                if uv.x < 0.5 {
+                    uv.x += 1.0;
+                }
+            }
+        }
+
+        let indices: Vec<i32> = (0..3)
+            .map(|k| {
+                let key = (face[k], [uvs[k].x.to_bits(), uvs[k].y.to_bits()]);
+                *corners.entry(key).or_insert_with(|| {
+                    let point = points[face[k]];
+                    builder.vertex(point * radius, point, uvs[k])
+                })
+            })
+            .collect();
+        builder.triangle(indices[0], indices[1], indices[2]);
+    }
+
+    builder.build()
+}
+
+/// A closed cylinder standing on the xz plane through its middle
+pub fn cylinder(radius: f32, height: f32, segments: usize) -> Model {
+    assert!(segments >= 3, "cylinder needs 3 segments");
+    let mut builder = Builder::default();
+    let half = height / 2.0;
+    for (row, y) in [half, -half].into_iter().enumerate() {
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let normal = around(u * TAU);
+            builder.vertex(
+                normal * radius + Vec3::y() * y,
+                normal,
+                Vec2::new(u, row as f32),
+            );
+        }
+    }
+    builder.grid(0, 1, segments);
+    add_cap(&mut builder, radius, half, segments, true);
+    add_cap(&mut builder, radius, -half, segments, false);
+
+    builder.build()
+}
+
+/// A closed cone with its tip pointing up
+pub fn cone(radius: f32, height: f32, segments: usize) -> Model {
+    assert!(segments >= 3, "cone needs 3 segments");
+    let mut builder = Builder::default();
+    let half = height / 2.0;
+    // The side leans back by the slope, so its normals tilt up
+    let normal = |angle: f32| around(angle) * height + Vec3::y() * radius;
+    for segment in 0..segments {
+        // Every segment gets its own tip so the normals can differ
+        let u = (segment as f32 + 0.5) / segments as f32;
+        let tip = builder.vertex(Vec3::y() * half, normal(u * TAU), Vec2::new(u, 0.0));
+        let [right, left] = [segment + 1, segment].map(|k| {
+            let u = k as f32 / segments as f32;
+            let position = around(u * TAU) * radius - Vec3::y() * half;
+            builder.vertex(position, normal(u * TAU), Vec2::new(u, 1.0))
+        });
+        builder.triangle(tip, right, left);
+    }
+    add_cap(&mut builder, radius, -half, segments, false);
+
+    builder.build()
+}
+
+/// Closes a circular opening at height `y` with a fan facing up or down
+fn add_cap(builder: &mut Builder, radius: f32, y: f32, segments: usize, up: bool) {
+    let normal = if up { Vec3::y() } else { -Vec3::y() };
+    let planar = |point: Vec3| Vec2::new(0.5 + point.x / 2.0, 0.5 + point.z / 2.0);
+    let center = builder.vertex(Vec3::y() * y, normal, Vec2::new(0.5, 0.5));
+    let first = center + 1;
+    for segment in 0..=segments {
+        let point = around(segment as f32 / segments as f32 * TAU);
+        builder.vertex(point * radius + Vec3::y() * y, normal, planar(point));
+    }
+    for segment in 0..segments as i32 {
+        let (a, b) = (first + segment, first + segment + 1);
+        match up {
+            true => builder.triangle(center, b, a),
+            false => builder.triangle(center, a, b),
+        }
+    }
+}
+
+/// A ring lying in the xz plane. `major_radius` reaches the middle of the
+/// tube and `minor_radius` is the thickness of the tube.
+pub fn torus(
+    major_radius: f32,
+    minor_radius: f32,
+    major_segments: usize,
+    minor_segments: usize,
+) -> Model {
+    assert!(
+        major_segments >= 3 && minor_segments >= 3,
+        "torus needs 3 segments each way"
+    );
+    let mut builder = Builder::default();
+    for row in 0..=minor_segments {
+        let v = row as f32 / minor_segments as f32;
+        // Rows go down the outside of the tube first
+        let tube = -v * TAU;
+        for segment in 0..=major_segments {
+            let u = segment as f32 / major_segments as f32;
+            let outwards = around(u * TAU);
+            let normal = outwards * tube.cos() + Vec3::y() * tube.sin();
+            builder.vertex(
+                outwards * major_radius + normal * minor_radius,
+                normal,
+                Vec2::new(u, v),
+            );
+        }
+    }
+    builder.grid(0, minor_segments, major_segments);
+
+    builder.build()
+}
+
+/// A flat grid in the xz plane facing up, split into `columns` along x and
+/// `rows` along z
+pub fn plane(width: f32, depth: f32, columns: usize, rows: usize) -> Model {
+    assert!(columns >= 1 && rows >= 1, "plane needs a row and a column");
+    let mut builder = Builder::default();
+    for row in 0..=rows {
+        let v = row as f32 / rows as f32;
+        for column in 0..=columns {
+            let u = column as f32 / columns as f32;
+            let position = Vec3::new((u - 0.5) * width, 0.0, (0.5 - v) * depth);
+            builder.vertex(position, Vec3::y(), Vec2::new(u, v));
+        }
+    }
+    builder.grid(0, rows, columns);
+
+    builder.build()
+}
+
+/// A cylinder of given `height` with a half sphere on each end. Each half
+/// sphere is made of `rings` bands.
+pub fn capsule(radius: f32, height: f32, segments: usize, rings: usize) -> Model {
+    assert!(
+        segments >= 3 && rings >= 1,
+        "capsule needs 3 segments and a ring"
+    );
+    let mut builder = Builder::default();
+    let half = height / 2.0;
+    // Texture coordinates run down the surface by distance travelled
+    let length = PI * radius + height;
+
+    let mut row_count = 0;
+    for (offset, start) in [(half, 0.0), (-half, PI / 2.0)] {
+        for ring in 0..=rings {
+            let polar = start + ring as f32 / rings as f32 * PI / 2.0;
+            let distance = polar * radius + if offset < 0.0 { height } else { 0.0 };
+            for segment in 0..=segments {
+                let u = segment as f32 / segments as f32;
+                let normal = meridian(u * TAU, polar);
+                builder.vertex(
+                    normal * radius + Vec3::y() * offset,
+                    normal,
+                    Vec2::new(u, distance / length),
+                );
+            }
+            row_count += 1;
+        }
+    }
+    builder.grid(0, row_count - 1, segments);
+
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::model::validate;
+
+    fn shapes() -> Vec<(&'static str, Model, bool)> {
+        // Name, model and whether it is convex
+        vec![
+            ("uv_sphere", uv_sphere(1.0, 12, 6), true),
+            ("icosphere", icosphere(1.0, 2), true),
+            ("cylinder", cylinder(1.0, 2.0, 12), true),
+            ("cone", cone(1.0, 2.0, 12), true),
+            ("torus", torus(1.0, 0.3, 12, 8), false),
+            ("plane", plane(2.0, 1.0, 3, 2), false),
+            ("capsule", capsule(0.5, 2.0, 12, 4), true),
+        ]
+    }
+
+    #[test]
+    fn triangles_are_valid() {
+        for (name, model, _) in shapes() {
+            let positions: Vec<Vec4> = model.vertices.iter().map(|v| v.position).collect();
+            let errors = validate(&positions, &model.triangles);
+            assert!(errors.is_empty(), "{name}: {}", errors[0]);
+        }
+    }
+
+    #[test]
+    fn normals_are_unit_length() {
+        for (name, model, _) in shapes() {
+            for vertex in &model.vertices {
+                let length = vertex.normal.unwrap().norm();
+                assert!((length - 1.0).abs() < 1e-5, "{name}: {length}");
+            }
+        }
+    }
+
+    #[test]
+    fn winding_agrees_with_normals() {
+        for (name, model, _) in shapes() {
+            for (triangle, face) in model.triangles.iter().zip(model.face_normals()) {
+                let normals: Vec3 = triangle
+                    .vertices
+                    .iter()
+                    .map(|&i| model.vertices[i as usize].normal.unwrap())
+                    .sum();
+                assert!(face.dot(&normals) > 0.0, "{name}: {:?}", triangle.vertices);
+            }
+        }
+    }
+
+    #[test]
+    fn convex_shapes_face_outward() {
+        for (name, model, convex) in shapes() {
+            if !convex {
+                continue;
+            }
+            for (triangle, face) in model.triangles.iter().zip(model.face_normals()) {
+                let centroid: Vec3 = triangle
+                    .vertices
+                    .iter()
+                    .map(|&i| model.vertices[i as usize].position.xyz())
+                    .sum();
+                assert!(face.dot(&centroid) > 0.0, "{name}: {:?}", triangle.vertices);
+            }
+        }
+    }
+
+    #[test]
+    fn torus_faces_away_from_its_ring() {
+        let model = torus(1.0, 0.3, 12, 8);
+        for (triangle, face) in model.triangles.iter().zip(model.face_normals()) {
+            let centroid: Vec3 = triangle
+                .vertices
+                .iter()
+                .map(|&i| model.vertices[i as usize].position.xyz())
+                .sum::<Vec3>()
+                / 3.0;
+            let ring = Vec3::new(centroid.x, 0.0, centroid.z).normalize();
+            assert!(
+                face.dot(&(centroid - ring)) > 0.0,
+                "{:?}",
+                triangle.vertices
+            );
+        }
+    }
+
+    #[test]
+    fn plane_faces_up() {
+        let model = plane(2.0, 1.0, 3, 2);
+        assert!(model.face_normals().iter().all(|normal| normal.y > 0.0));
+    }
+
+    #[test]
+    fn texture_coordinates_stay_in_range() {
+        for (name, model, _) in shapes() {
+            for vertex in &model.vertices {
+                let uv = vertex.uv.unwrap();
+                // Faces of the icosphere crossing the seam run a little past 1
+                let max_u = if name == "icosphere" { 1.5 } else { 1.0 };
+                assert!((0.0..=max_u).contains(&uv.x), "{name}: {uv}");
+                assert!((0.0..=1.0).contains(&uv.y), "{name}: {uv}");
+            }
+        }
+    }
+}
@@ -0,0 +1,394 @@
+//! STL models, read in both ASCII and binary form and written as binary.
+//!
+//! STL stores every triangle on its own, so identical positions are welded
+//! back into shared vertices while loading. Like OBJ, STL is right-handed
+//! with counter-clockwise front faces, so z is mirrored and faces are
+//! reversed on the way in and out.
+
+use crate::color;
+use crate::models::model::Model;
+use crate::models::triangle::Triangle;
+use crate::text::numbers;
+use nalgebra_glm::{Vec3, Vec4};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+/// Size of the binary header and triangle count
+const HEADER_LEN: usize = 84;
+/// Size of a binary triangle: normal, 3 vertices and an attribute count
+const TRIANGLE_LEN: usize = 50;
+
+#[derive(Debug)]
+pub enum StlError {
+    Io(std::io::Error),
+    /// ASCII that we could not make sense of, on a 1-based line number
+    Parse {
+        line: usize,
+        message: String,
+    },
+    /// Binary data that breaks the format, at a byte offset
+    Malformed {
+        offset: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for StlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StlError::Io(error) => write!(f, "{error}"),
+            StlError::Parse { line, message } => write!(f, "line {line}: {message}"),
+            StlError::Malformed { offset, reason } => {
+                write!(f, "malformed STL at byte {offset}: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for StlError {}
+
+impl From<std::io::Error> for StlError {
+    fn from(error: std::io::Error) -> Self {
+        StlError::Io(error)
+    }
+}
+
+/// Reads and decodes an STL file
+pub fn load(path: impl AsRef<Path>) -> Result<Model, StlError> {
+    decode(&std::fs::read(path)?)
+}
+
+/// Decodes ASCII or binary STL.
+///
+/// Binary files may also start with `solid`, so the size implied by the
+/// triangle count decides first, then whether the data is text at all.
+pub fn decode(bytes: &[u8]) -> Result<Model, StlError> {
+    let binary_len = bytes.get(80..HEADER_LEN).map(|count| {
+        HEADER_LEN + TRIANGLE_LEN * u32::from_le_bytes(count.try_into().unwrap()) as usize
+    });
+    match std::str::from_utf8(bytes) {
+        Ok(text) if binary_len != Some(bytes.len()) && text.trim_start().starts_with("solid") => {
+            parse(text)
+        }
+        _ => decode_binary(bytes),
+    }
+}
+
+fn decode_binary(bytes: &[u8]) -> Result<Model, StlError> {
+    let malformed = |offset: usize, reason: String| StlError::Malformed { offset, reason };
+    if bytes.len() < HEADER_LEN {
+        return Err(malformed(
+            bytes.len(),
+            "file is shorter than the header".to_string(),
+        ));
+    }
+    let count = u32::from_le_bytes(bytes[80..HEADER_LEN].try_into().unwrap()) as usize;
+    let available = (bytes.len() - HEADER_LEN) / TRIANGLE_LEN;
+    if available < count {
+        return Err(malformed(
+            HEADER_LEN + available * TRIANGLE_LEN,
+            format!("header promises {count} triangles but only {available} follow"),
+        ));
+    }
+
+    let mut welder = Welder::default();
+    for (i, data) in bytes[HEADER_LEN..]
+        .chunks_exact(TRIANGLE_LEN)
+        .take(count)
+        .enumerate()
+    {
+        let offset = HEADER_LEN + i * TRIANGLE_LEN;
+        let value = |k: usize| f32::from_le_bytes(data[k * 4..k * 4 + 4].try_into().unwrap());
+        let values: [f32; 12] = std::array::from_fn(value);
+        if let Some(k) = values.iter().position(|value| !value.is_finite()) {
+            return Err(malformed(offset + k * 4, "value is not finite".to_string()));
+        }
+        let [normal, v0, v1, v2] =
+            [0, 3, 6, 9].map(|k| Vec3::new(values[k], values[k + 1], values[k + 2]));
+        welder.add(normal, [v0, v1, v2]);
+    }
+
+    Ok(welder.into_model())
+}
+
+/// Parses ASCII STL. Loops with more than three vertices are triangulated as fans.
+pub fn parse(text: &str) -> Result<Model, StlError> {
+    let mut welder = Welder::default();
+    let mut normal: Option<Vec3> = None;
+    let mut corners: Vec<Vec3> = vec![];
+
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: String| StlError::Parse {
+            line: i + 1,
+            message,
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let vector = |words: &[&str]| {
+            let [x, y, z] = numbers::<3>(words).map_err(error)?;
+            match x.is_finite() && y.is_finite() && z.is_finite() {
+                true => Ok(Vec3::new(x, y, z)),
+                false => Err(error("value is not finite".to_string())),
+            }
+        };
+
+        match words.as_slice() {
+            [] => {}
+            ["solid", ..] | ["endsolid", ..] => {}
+            ["facet", "normal", rest @ ..] => {
+                if normal.is_some() {
+                    return Err(error("facet before the previous endfacet".to_string()));
+                }
+                normal = Some(vector(rest)?);
+            }
+            ["outer", "loop"] => corners.clear(),
+            ["vertex", rest @ ..] => {
+                if normal.is_none() {
+                    return Err(error("vertex outside of a facet".to_string()));
+                }
+                corners.push(vector(rest)?);
+            }
+            ["endloop"] => {
+                if corners.len() < 3 {
+                    return Err(error(format!(
+                        "loop needs at least 3 vertices, found {}",
+                        corners.len()
+                    )));
+                }
+            }
+            ["endfacet"] => {
+                let Some(facet_normal) = normal.take() else {
+                    return Err(error("endfacet without a facet".to_string()));
+                };
+                for j in 1..corners.len().saturating_sub(1) {
+                    welder.add(facet_normal, [corners[0], corners[j], corners[j + 1]]);
+                }
+                corners.clear();
+            }
+            [record, ..] => return Err(error(format!("unknown record {record}"))),
+        }
+    }
+    if normal.is_some() {
+        return Err(StlError::Parse {
+            line: text.lines().count(),
+            message: "file ends inside a facet".to_string(),
+        });
+    }
+
+    Ok(welder.into_model())
+}
+
+/// Writes a model as binary STL
+pub fn save(model: &Model, path: impl AsRef<Path>) -> std::io::Result<()> {
+    std::fs::write(path, encode(model))
+}
+
+/// Encodes a model as binary STL, using its facet normals when it has them
+pub fn encode(model: &Model) -> Vec<u8> {
+    let normals = match &model.facet_normals {
+        Some(normals) => normals.clone(),
+        None => model
+            .face_normals()
+            .iter()
+            .map(|normal| normal.try_normalize(f32::EPSILON).unwrap_or_default())
+            .collect(),
+    };
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + TRIANGLE_LEN * model.triangles.len());
+    let mut header = [0u8; 80];
+    let title = b"binary STL written by rasterizer";
+    header[..title.len()].copy_from_slice(title);
+    bytes.extend(header);
+    bytes.extend((model.triangles.len() as u32).to_le_bytes());
+    for (triangle, normal) in model.triangles.iter().zip(normals) {
+        let corners = triangle
+            .vertices
+            .map(|i| model.vertices[i as usize].position.xyz());
+        for vector in [normal, corners[0], corners[2], corners[1]] {
+            for value in [vector.x, vector.y, -vector.z] {
+                bytes.extend(value.to_le_bytes());
+            }
+        }
+        bytes.extend(0u16.to_le_bytes());
+    }
+
+    bytes
+}
+
+/// Collects triangles, sharing vertices between those with identical positions
+#[derive(Default)]
+struct Welder {
+    indices: HashMap<[u32; 3], i32>,
+    vertices: Vec<Vec4>,
+    triangles: Vec<Triangle>,
+    normals: Vec<Vec3>,
+}
+
+impl Welder {
+    /// Adds a triangle as stored in STL, mirroring it into our coordinates
+    fn add(&mut self, normal: Vec3, corners: [Vec3; 3]) {
+        let corners = [corners[0], corners[2], corners[1]]
+            .map(|corner| Vec3::new(corner.x, corner.y, -corner.z));
+        let indices = corners.map(|corner| {
+            // Adding zero turns -0.0 into 0.0 so both weld together
+            let key = [corner.x + 0.0, corner.y + 0.0, corner.z + 0.0].map(f32::to_bits);
+            *self.indices.entry(key).or_insert_with(|| {
+                self.vertices
+                    .push(Vec4::new(corner.x, corner.y, corner.z, 1.0));
+                (self.vertices.len() - 1) as i32
+            })
+        });
+
+        // Plenty of exporters leave the normal zeroed, so fall back to the winding
+        let normal = Vec3::new(normal.x, normal.y, -normal.z)
+            .try_normalize(f32::EPSILON)
+            .or_else(|| {
+                (corners[1] - corners[0])
+                    .cross(&(corners[2] - corners[0]))
+                    .try_normalize(f32::EPSILON)
+            })
+            .unwrap_or_default();
+
+        self.triangles.push(Triangle::new(
+            indices[0],
+            indices[1],
+            indices[2],
+            color::WHITE,
+        ));
+        self.normals.push(normal);
+    }
+
+    /// Builds the model, leaving out facets without area and repeated ones
+    fn into_model(self) -> Model {
+        let (model, skipped) =
+            Model::try_new_lenient(self.vertices, self.triangles).expect("welded vertices exist");
+        let mut next = skipped.iter().peekable();
+        let normals = self
+            .normals
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| next.next_if_eq(&i).is_none())
+            .map(|(_, normal)| normal)
+            .collect();
+        model.with_facet_normals(normals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::model::default_cube;
+
+    const ASCII: &str = "solid square
+facet normal 0 0 1
+  outer loop
+    vertex 0 0 0
+    vertex 1 0 0
+    vertex 1 1 0
+    vertex 0 1 0
+  endloop
+endfacet
+endsolid square
+";
+
+    fn corners(model: &Model) -> Vec<[Vec3; 3]> {
+        model
+            .triangles
+            .iter()
+            .map(|t| {
+                t.vertices
+                    .map(|i| model.vertices[i as usize].position.xyz())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_ascii_and_welds_vertices() {
+        let model = decode(ASCII.as_bytes()).unwrap();
+        assert_eq!(model.triangles.len(), 2);
+        assert_eq!(model.vertices.len(), 4);
+        // The normal is mirrored along with the positions
+        let normals = model.facet_normals.as_ref().unwrap();
+        assert!(normals
+            .iter()
+            .all(|&normal| normal == Vec3::new(0.0, 0.0, -1.0)));
+        assert!(model.face_normals().iter().all(|normal| normal.z < 0.0));
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let cube = default_cube();
+        let bytes = encode(&cube);
+        assert_eq!(bytes.len(), HEADER_LEN + TRIANGLE_LEN * 12);
+        let model = decode(&bytes).unwrap();
+        assert_eq!(model.vertices.len(), 8);
+        assert_eq!(corners(&model), corners(&cube));
+    }
+
+    #[test]
+    fn binary_may_start_with_solid() {
+        let mut bytes = encode(&default_cube());
+        bytes[..6].copy_from_slice(b"solid ");
+        assert_eq!(decode(&bytes).unwrap().triangles.len(), 12);
+    }
+
+    #[test]
+    fn zero_normals_follow_the_winding() {
+        let model = parse(&ASCII.replace("normal 0 0 1", "normal 0 0 0")).unwrap();
+        let normals = model.facet_normals.as_ref().unwrap();
+        assert!(normals
+            .iter()
+            .all(|&normal| normal == Vec3::new(0.0, 0.0, -1.0)));
+    }
+
+    #[test]
+    fn skips_facets_without_area() {
+        let text = ASCII.replace("vertex 1 1 0\n    vertex 0 1 0", "vertex 2 0 0");
+        let model = parse(&text).unwrap();
+        assert!(model.triangles.is_empty());
+        assert_eq!(model.facet_normals.as_ref().unwrap().len(), 0);
+        assert_eq!(model.warnings.len(), 1);
+    }
+
+    #[test]
+    fn malformed_binary_is_an_error() {
+        let bytes = encode(&default_cube());
+        for (bytes, offset) in [
+            (&bytes[..50], 50),
+            (&bytes[..bytes.len() - 1], HEADER_LEN + 11 * TRIANGLE_LEN),
+        ] {
+            match decode(bytes) {
+                Err(StlError::Malformed { offset: found, .. }) => assert_eq!(found, offset),
+                _ => panic!("{} bytes should not decode", bytes.len()),
+            }
+        }
+
+        let mut bytes = bytes;
+        bytes[HEADER_LEN + 16..HEADER_LEN + 20].copy_from_slice(&f32::NAN.to_le_bytes());
+        assert!(matches!(
+            decode(&bytes),
+            Err(StlError::Malformed { offset, .. }) if offset == HEADER_LEN + 16
+        ));
+    }
+
+    #[test]
+    fn malformed_ascii_is_an_error() {
+        for (text, line) in [
+            ("solid\nvertex 0 0 0\n", 2),
+            ("solid\nfacet normal 0 0 1\nouter loop\nvertex 0 0\n", 4),
+            (
+                "solid\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nendloop\n",
+                5,
+            ),
+            ("solid\nfacet normal 0 0 inf\n", 2),
+            ("solid\nendfacet\n", 2),
+            ("solid\nbogus\n", 2),
+            ("solid\nfacet normal 0 0 1\n", 2),
+        ] {
+            match parse(text) {
+                Err(StlError::Parse { line: found, .. }) => assert_eq!(found, line, "{text}"),
+                _ => panic!("{text} should not parse"),
+            }
+        }
+    }
+}
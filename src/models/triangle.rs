@@ -1,15 +1,50 @@
 use crate::color::Color;
+use crate::models::material::Material;
+use crate::rendering::texture::Texture;
+use std::rc::Rc;
 
 #[derive(Clone)]
 pub struct Triangle {
     pub vertices: [i32; 3],
     pub color: Color,
+    /// Specular exponent. Higher is shinier, `None` is matte.
+    pub specular: Option<f32>,
+    /// Color of highlights, that of the surface itself when `None`
+    pub specular_color: Option<Color>,
+    /// Replaces the color when the model has texture coordinates
+    pub texture: Option<Rc<Texture>>,
+    /// Where the color, specular and texture came from, if anywhere
+    pub material: Option<Rc<Material>>,
 }
 impl Triangle {
     pub fn new(idx0: i32, idx1: i32, idx2: i32, color: Color) -> Self {
         Self {
             vertices: [idx0, idx1, idx2],
             color,
+            specular: None,
+            specular_color: None,
+            texture: None,
+            material: None,
         }
     }
+
+    pub fn with_specular(mut self, specular: f32) -> Self {
+        self.specular = Some(specular);
+        self
+    }
+
+    pub fn with_texture(mut self, texture: Rc<Texture>) -> Self {
+        self.texture = Some(texture);
+        self
+    }
+
+    /// Takes color, specular exponent and color, and texture from given material
+    pub fn with_material(mut self, material: Rc<Material>) -> Self {
+        self.color = material.diffuse;
+        self.specular = material.get_specular_exponent();
+        self.specular_color = Some(material.specular);
+        self.texture = material.diffuse_map.clone();
+        self.material = Some(material);
+        self
+    }
 }
@@ -0,0 +1,92 @@
+use crate::color::Color;
+use nalgebra_glm::{Vec2, Vec3, Vec4};
+use std::ops::Range;
+
+/// A corner of a model along with everything that gets interpolated across
+/// the triangles using it.
+///
+/// Attributes a vertex doesn't have fall back to those of its triangle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Vertex {
+    pub position: Vec4,
+    /// Unit normal used instead of the face normal when lighting
+    pub normal: Option<Vec3>,
+    pub uv: Option<Vec2>,
+    /// Overrides the color of the triangle at this vertex
+    pub color: Option<Color>,
+    /// Scales the shaded color
+    pub intensity: f32,
+    /// Values of the extra attributes, laid out as in the [`VertexLayout`]
+    /// of the model
+    pub extra: Vec<f32>,
+}
+
+impl Vertex {
+    pub fn new(position: &Vec4) -> Self {
+        Self {
+            position: *position,
+            normal: None,
+            uv: None,
+            color: None,
+            intensity: 1.0,
+            extra: vec![],
+        }
+    }
+
+    /// Blends every attribute towards `other` by `t` (0 is self, 1 is other).
+    ///
+    /// Optional attributes are only kept if both vertices have them.
+    pub fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            position: self.position.lerp(&other.position, t),
+            normal: self.normal.zip(other.normal).map(|(a, b)| a.lerp(&b, t)),
+            uv: self.uv.zip(other.uv).map(|(a, b)| a.lerp(&b, t)),
+            color: self.color.zip(other.color).map(|(a, b)| a.lerp(b, t)),
+            intensity: self.intensity + t * (other.intensity - self.intensity),
+            extra: self
+                .extra
+                .iter()
+                .zip(&other.extra)
+                .map(|(a, b)| a + t * (b - a))
+                .collect(),
+        }
+    }
+}
+
+/// Names and sizes of the extra attributes every vertex of a model carries
+/// in [`Vertex::extra`], one after the other
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VertexLayout {
+    attributes: Vec<(String, usize)>,
+}
+
+impl VertexLayout {
+    /// Appends an attribute of `size` values
+    pub fn push(&mut self, name: &str, size: usize) {
+        self.attributes.push((name.to_string(), size));
+    }
+
+    /// Where the values of the named attribute sit in [`Vertex::extra`]
+    pub fn get_range(&self, name: &str) -> Option<Range<usize>> {
+        let mut start = 0;
+        for (attribute, size) in &self.attributes {
+            if attribute == name {
+                return Some(start..start + size);
+            }
+            start += size;
+        }
+        None
+    }
+
+    /// Number of extra values per vertex
+    pub fn get_size(&self) -> usize {
+        self.attributes.iter().map(|(_, size)| size).sum()
+    }
+
+    /// Attribute names and sizes in order
+    pub fn attributes(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.attributes
+            .iter()
+            .map(|(name, size)| (name.as_str(), *size))
+    }
+}
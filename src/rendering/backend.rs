@@ -0,0 +1,81 @@
+use crate::rendering::framebuffer::Framebuffer;
+#[cfg(feature = "sdl")]
+use sdl2::pixels::PixelFormatEnum;
+#[cfg(feature = "sdl")]
+use sdl2::render::{Texture, TextureCreator};
+#[cfg(feature = "sdl")]
+use sdl2::video::{Window, WindowContext};
+
+/// Something that can present a finished [`Framebuffer`].
+pub trait Backend {
+    fn present(&mut self, framebuffer: &Framebuffer);
+}
+
+/// Keeps frames in memory only. Used for offscreen rendering.
+pub struct HeadlessBackend;
+
+impl Backend for HeadlessBackend {
+    fn present(&mut self, _framebuffer: &Framebuffer) {}
+}
+
+/// Presents frames in an SDL2 window.
+#[cfg(feature = "sdl")]
+pub struct SdlBackend {
+    canvas: sdl2::render::Canvas<Window>,
+    texture_creator: TextureCreator<WindowContext>,
+    /// Streaming texture the frames are uploaded into, sized like the last
+    /// framebuffer presented
+    texture: Option<(Texture, usize, usize)>,
+}
+
+#[cfg(feature = "sdl")]
+impl SdlBackend {
+    pub fn new(window: Window) -> Self {
+        let canvas = window.into_canvas().accelerated().build().unwrap();
+        let texture_creator = canvas.texture_creator();
+        Self {
+            canvas,
+            texture_creator,
+            texture: None,
+        }
+    }
+
+    /// The streaming texture for a framebuffer of the given size, created
+    /// again only when the size changes
+    fn texture(&mut self, width: usize, height: usize) -> &mut Texture {
+        if !matches!(self.texture, Some((_, w, h)) if (w, h) == (width, height)) {
+            if let Some((old, _, _)) = self.texture.take() {
+                // SAFETY: the canvas owning the texture is still alive.
+                unsafe { old.destroy() };
+            }
+            let texture = self
+                .texture_creator
+                .create_texture_streaming(PixelFormatEnum::RGB888, width as u32, height as u32)
+                .unwrap();
+            self.texture = Some((texture, width, height));
+        }
+        &mut self.texture.as_mut().unwrap().0
+    }
+}
+
+#[cfg(feature = "sdl")]
+impl Backend for SdlBackend {
+    /// Uploads the framebuffer into the streaming texture and swaps it onto the window
+    fn present(&mut self, framebuffer: &Framebuffer) {
+        let width = framebuffer.width();
+        let texture = self.texture(width, framebuffer.height());
+        texture
+            .with_lock(None, |buffer: &mut [u8], pitch: usize| {
+                for (y, row) in framebuffer.pixels().chunks(width).enumerate() {
+                    for (x, pixel) in row.iter().enumerate() {
+                        let offset = y * pitch + x * 4;
+                        buffer[offset..offset + 4].copy_from_slice(&pixel.to_ne_bytes());
+                    }
+                }
+            })
+            .unwrap();
+        let texture = &self.texture.as_ref().unwrap().0;
+        self.canvas.copy(texture, None, None).unwrap();
+        self.canvas.present();
+    }
+}
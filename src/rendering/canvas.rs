@@ -1,172 +1,217 @@
 use crate::color::Color;
-use nalgebra_glm::Vec3;
-use sdl2::rect::Point;
-use sdl2::video::Window;
+use crate::image::{Image, ImageError};
+use crate::rendering::backend::{Backend, HeadlessBackend};
+use crate::rendering::framebuffer::{DepthTest, Framebuffer};
+use crate::rendering::texture::Texture;
+use nalgebra_glm::{Vec2, Vec3};
 
-/// An SDL2 [`Canvas<Window>`](sdl2::render::Canvas<Window>) simplified for the book.
+/// Interpolated attributes at a pixel along with how fast they change there
+pub struct Fragment<'a> {
+    pub values: &'a [f32],
+    /// Change of each value one pixel to the right
+    pub ddx: &'a [f32],
+    /// Change of each value one pixel up
+    pub ddy: &'a [f32],
+}
+
+impl Fragment<'_> {
+    /// Samples a texture with the texture coordinates stored at `values[i]`
+    /// and `values[i + 1]`, using their derivatives to pick the mip level
+    pub fn sample(&self, texture: &Texture, i: usize) -> Color {
+        texture.sample_grad(
+            &Vec2::new(self.values[i], self.values[i + 1]),
+            &Vec2::new(self.ddx[i], self.ddx[i + 1]),
+            &Vec2::new(self.ddy[i], self.ddy[i + 1]),
+        )
+    }
+}
+
+/// A software [`Framebuffer`] simplified for the book.
+///
+/// All drawing happens in memory. The [`Backend`] only gets to see the
+/// finished frame when [`Canvas::present`] is called.
 pub struct Canvas {
-    canvas: sdl2::render::Canvas<Window>,
+    framebuffer: Framebuffer,
+    backend: Box<dyn Backend>,
     pub width: i32,
     pub height: i32,
+    pub depth_test: DepthTest,
 }
 
 impl Canvas {
-    pub fn new(window: Window, width: i32, height: i32) -> Self {
+    pub fn new(backend: Box<dyn Backend>, width: i32, height: i32) -> Self {
         Self {
-            canvas: window.into_canvas().accelerated().build().unwrap(),
+            framebuffer: Framebuffer::new(width as usize, height as usize),
+            backend,
             width,
             height,
+            depth_test: DepthTest::Less,
         }
     }
 
-    /// Swap the presented and currently drawn to buffers
-    pub fn present(&mut self) {
-        self.canvas.present();
+    /// Creates a canvas that never leaves memory
+    pub fn headless(width: i32, height: i32) -> Self {
+        Self::new(Box::new(HeadlessBackend), width, height)
     }
 
-    /// Clears the canvas with given color.
-    pub fn clear(&mut self, color: Color) {
-        self.canvas.set_draw_color(color);
-        self.canvas.clear();
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
     }
 
-    /// Draws a pixel of given color at given coordinates on the canvas.
-    ///
-    /// Converts given coordinates from
-    /// "Screen Space" (origin at center, +x left, +y up) to
-    /// "Canvas Space" (origin at top left, +x left, +y down)
-    pub fn put_pixel(&mut self, point: &Vec3, color: Color) {
-        self.canvas.set_draw_color(color);
-        // Convert from textbook screen space to sdl2 canvas space
-        self.canvas
-            .draw_point(Point::new(
-                (self.width / 2) + point.x as i32,
-                (self.height / 2) - point.y as i32,
-            ))
-            .unwrap();
+    /// Hands the current frame to the backend
+    pub fn present(&mut self) {
+        self.backend.present(&self.framebuffer);
     }
 
-    /// Draws gradient triangle
-    ///
-    /// Uses interpolation to determine which pixels to draw and which color
-    /// intensity to use for those pixels (between black and given color).
-    pub fn draw_gradient_triangle<'a>(
-        &mut self,
-        mut p0: &'a Vec3,
-        p0h: f32,
-        mut p1: &'a Vec3,
-        p1h: f32,
-        mut p2: &'a Vec3,
-        p2h: f32,
-        color: Color,
-    ) {
-        // Organize points by y level. P0 <= P1 <= P2
-        if p1.y < p0.y {
-            (p0, p1) = (p1, p0);
-        }
-        if p2.y < p0.y {
-            (p0, p2) = (p2, p0);
-        }
-        if p2.y < p1.y {
-            (p1, p2) = (p2, p1);
+    /// Copies the current frame into an [`Image`]
+    pub fn to_image(&self) -> Image {
+        Image {
+            width: self.framebuffer.width(),
+            height: self.framebuffer.height(),
+            pixels: self
+                .framebuffer
+                .pixels()
+                .iter()
+                .map(|&pixel| Color::from(pixel))
+                .collect(),
         }
+    }
 
-        // Compute x's for each row in the triangle
-        let x01 = self.interpolate(p0.y, p0.x, p1.y, p1.x);
-        let h01 = self.interpolate(p0.y, p0h, p1.y, p1h);
-
-        let x12 = self.interpolate(p1.y, p1h, p2.y, p2h);
-        let h12 = self.interpolate(p1.y, p1h, p2.y, p2h);
-
-        let x02 = self.interpolate(p0.y, p0.x, p2.y, p2.x);
-        let h02 = self.interpolate(p0.y, p0h, p2.y, p2h);
-
-        // x01 and x12 have a common point so we remove one from x01 and join them
-        let x012 = [&x01[..x01.len() - 1], &x12[..]].concat();
-        let h012 = [&h01[..h01.len() - 1], &h12[..]].concat();
-
-        // Figure out which array is left and which is right
-        let x_left: Vec<f32>;
-        let h_left: Vec<f32>;
+    /// The current frame as `[r, g, b, ...]`, top left first
+    pub fn to_rgb(&self) -> Vec<u8> {
+        self.to_image().to_rgb()
+    }
 
-        let x_right: Vec<f32>;
-        let h_right: Vec<f32>;
+    /// The current frame as `[r, g, b, a, ...]`, top left first
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.to_image().to_rgba()
+    }
 
-        let m = x02.len() / 2;
-        if x02[m] < x012[m] {
-            x_left = x02;
-            h_left = h02;
+    /// Saves the current frame as PNG or PPM depending on the file extension
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<(), ImageError> {
+        self.to_image().save(path)
+    }
 
-            x_right = x012;
-            h_right = h012;
-        } else {
-            x_left = x012;
-            h_left = h012;
+    /// Clears the canvas with given color and resets the depth buffer.
+    pub fn clear(&mut self, color: Color) {
+        self.framebuffer.clear(color);
+        self.framebuffer.clear_depth();
+    }
 
-            x_right = x02;
-            h_right = h02;
+    /// Draws a pixel of given color at given coordinates on the canvas.
+    ///
+    /// Converts given coordinates from
+    /// "Screen Space" (origin at center, +x left, +y up) to
+    /// "Canvas Space" (origin at top left, +x left, +y down)
+    ///
+    /// The z coordinate holds 1/z of the point and is checked against the
+    /// depth buffer using [`Canvas::depth_test`]. The pixel is dropped if it fails.
+    pub fn put_pixel(&mut self, point: &Vec3, color: Color) {
+        // Convert from textbook screen space to framebuffer canvas space
+        let x = (self.width / 2) + point.x as i32;
+        let y = (self.height / 2) - point.y as i32;
+        if self.framebuffer.test_depth(x, y, point.z, self.depth_test) {
+            self.framebuffer.set_pixel(x, y, color);
         }
+    }
 
-        for y in (p0.y as i32)..=(p2.y as i32) {
-            let idx = (y - p0.y as i32) as usize;
-            let x_l = x_left[idx];
-            let x_r = x_right[idx];
-
-            let h_segment = self.interpolate(x_l, h_left[idx], x_r, h_right[idx]);
-            for x in (x_l as i32)..=(x_r as i32) {
-                let shaded_color = color * h_segment[(x - x_l as i32) as usize];
-                self.put_pixel(&Vec3::new(x as f32, y as f32, 1.0), shaded_color);
-            }
-        }
+    /// Checks a point against the depth buffer without touching it
+    fn passes_depth(&self, point: &Vec3) -> bool {
+        let x = (self.width / 2) + point.x as i32;
+        let y = (self.height / 2) - point.y as i32;
+        self.framebuffer
+            .passes_depth(x, y, point.z, self.depth_test)
     }
 
-    /// Draws filled triangle
+    /// Draws a triangle, letting `shade` pick the color of every pixel.
     ///
-    /// Uses interpolation to determine which pixels to draw inside the triangle
-    pub fn draw_filled_triangle<'a>(
+    /// Each corner carries the same number of attributes. They are
+    /// interpolated perspective-correctly: multiplied by 1/z at the corners,
+    /// interpolated linearly across the screen and divided by the
+    /// interpolated 1/z at each pixel. `shade` only runs for pixels that
+    /// pass the depth test, and pixels it returns `None` for are skipped.
+    pub fn draw_interpolated_triangle<F>(
         &mut self,
-        mut p0: &'a Vec3,
-        mut p1: &'a Vec3,
-        mut p2: &'a Vec3,
-        color: Color,
-    ) {
+        points: [&Vec3; 3],
+        attributes: [&[f32]; 3],
+        mut shade: F,
+    ) where
+        F: FnMut(&Fragment) -> Option<Color>,
+    {
+        let [mut p0, mut p1, mut p2] = points;
+        let [mut a0, mut a1, mut a2] = attributes;
         // Organize points by y level. P0 <= P1 <= P2
         if p1.y < p0.y {
             (p0, p1) = (p1, p0);
+            (a0, a1) = (a1, a0);
         }
         if p2.y < p0.y {
             (p0, p2) = (p2, p0);
+            (a0, a2) = (a2, a0);
         }
         if p2.y < p1.y {
             (p1, p2) = (p2, p1);
+            (a1, a2) = (a2, a1);
         }
 
-        // Compute x's for each row in the triangle
-        let x01 = self.interpolate(p0.y, p0.x, p1.y, p1.x);
-        let x12 = self.interpolate(p1.y, p1.x, p2.y, p2.x);
-        let x02 = self.interpolate(p0.y, p0.x, p2.y, p2.x);
+        // Compute x's, depths and attributes over z for each row in the triangle
+        let (x02, x012) = self.edge_interpolate(p0.y, p0.x, p1.y, p1.x, p2.y, p2.x);
+        let (z02, z012) = self.edge_interpolate(p0.y, p0.z, p1.y, p1.z, p2.y, p2.z);
+        let (a02, a012): (Vec<Vec<f32>>, Vec<Vec<f32>>) = (0..a0.len())
+            .map(|i| {
+                self.edge_interpolate(p0.y, a0[i] * p0.z, p1.y, a1[i] * p1.z, p2.y, a2[i] * p2.z)
+            })
+            .unzip();
 
-        // x01 and x12 have a common point so we remove one from x01 and join them
-        let x012 = [&x01[..x01.len() - 1], &x12[..]].concat();
-
-        // Figure out which array is left and which is right
-        let x_left: Vec<f32>;
-        let x_right: Vec<f32>;
+        // Figure out which arrays are left and which are right
         let m = x02.len() / 2;
-        if x02[m] < x012[m] {
-            x_left = x02;
-            x_right = x012;
+        let (x_left, z_left, a_left, x_right, z_right, a_right) = if x02[m] < x012[m] {
+            (x02, z02, a02, x012, z012, a012)
         } else {
-            x_left = x012;
-            x_right = x02;
-        }
+            (x012, z012, a012, x02, z02, a02)
+        };
 
+        let mut values = vec![0.0; a0.len()];
+        let mut ddx = vec![0.0; a0.len()];
+        let mut ddy = vec![0.0; a0.len()];
         for y in (p0.y as i32)..=(p2.y as i32) {
             let idx = (y - p0.y as i32) as usize;
             let x_l = x_left[idx];
             let x_r = x_right[idx];
+            // Row used to see how the attributes change vertically
+            let (next, sign) = if idx + 1 < x_left.len() {
+                (idx + 1, 1.0)
+            } else if idx > 0 {
+                (idx - 1, -1.0)
+            } else {
+                (idx, 0.0)
+            };
+            let (nx_l, nx_r) = (x_left[next], x_right[next]);
+
+            let z_segment = self.interpolate(x_l, z_left[idx], x_r, z_right[idx]);
             for x in (x_l as i32)..=(x_r as i32) {
-                self.put_pixel(&Vec3::new(x as f32, y as f32, 1.0), color);
+                let point = Vec3::new(x as f32, y as f32, z_segment[(x - x_l as i32) as usize]);
+                if !self.passes_depth(&point) {
+                    continue;
+                }
+                let z_dx = value_on_row(x_l, z_left[idx], x_r, z_right[idx], x + 1);
+                let z_dy = value_on_row(nx_l, z_left[next], nx_r, z_right[next], x);
+                for i in 0..values.len() {
+                    let (left, right) = (&a_left[i], &a_right[i]);
+                    values[i] = value_on_row(x_l, left[idx], x_r, right[idx], x) / point.z;
+                    ddx[i] =
+                        value_on_row(x_l, left[idx], x_r, right[idx], x + 1) / z_dx - values[i];
+                    ddy[i] = sign
+                        * (value_on_row(nx_l, left[next], nx_r, right[next], x) / z_dy - values[i]);
+                }
+                let color = shade(&Fragment {
+                    values: &values,
+                    ddx: &ddx,
+                    ddy: &ddy,
+                });
+                if let Some(color) = color {
+                    self.put_pixel(&point, color);
+                }
             }
         }
     }
@@ -178,6 +223,30 @@ impl Canvas {
         self.draw_line(p2, p0, color);
     }
 
+    /// Interpolates a value along the edges of a triangle sorted by y.
+    ///
+    /// Returns the values along the long edge (P0 to P2) followed by the
+    /// values along the two short edges (P0 to P1 to P2) joined together.
+    #[allow(clippy::too_many_arguments)]
+    pub fn edge_interpolate(
+        &mut self,
+        y0: f32,
+        v0: f32,
+        y1: f32,
+        v1: f32,
+        y2: f32,
+        v2: f32,
+    ) -> (Vec<f32>, Vec<f32>) {
+        let v01 = self.interpolate(y0, v0, y1, v1);
+        let v12 = self.interpolate(y1, v1, y2, v2);
+        let v02 = self.interpolate(y0, v0, y2, v2);
+
+        // v01 and v12 have a common point so we remove one from v01 and join them
+        let v012 = [&v01[..v01.len() - 1], &v12[..]].concat();
+
+        (v02, v012)
+    }
+
     /// Computes set of points between two points
     pub fn interpolate(&mut self, i0: f32, d0: f32, i1: f32, d1: f32) -> Vec<f32> {
         if i0 == i1 {
@@ -188,7 +257,7 @@ impl Canvas {
             let mut d = d0;
             for _ in (i0 as i32)..=(i1 as i32) {
                 values.push(d);
-                d = d + a;
+                d += a;
             }
 
             values
@@ -198,7 +267,7 @@ impl Canvas {
     /// Draws a line of given color between given points.
     ///
     /// Computes y coordinate for each x coordinate using parameterized
-    /// line function.
+    /// line function. Depth (1/z) is interpolated the same way.
     ///
     /// Uses floats throughout computation and converts to integer at the end.
     pub fn draw_line<'a>(&mut self, mut p0: &'a Vec3, mut p1: &'a Vec3, color: Color) {
@@ -209,11 +278,10 @@ impl Canvas {
                 (p0, p1) = (p1, p0);
             }
             let ys = self.interpolate(p0.x, p0.y, p1.x, p1.y);
+            let zs = self.interpolate(p0.x, p0.z, p1.x, p1.z);
             for x in (p0.x as i32)..=(p1.x as i32) {
-                self.put_pixel(
-                    &Vec3::new(x as f32, ys[(x - p0.x as i32) as usize], 1.0),
-                    color,
-                );
+                let idx = (x - p0.x as i32) as usize;
+                self.put_pixel(&Vec3::new(x as f32, ys[idx], zs[idx]), color);
             }
         } else {
             // Compute x in terms of y so we can draw vertical lines
@@ -221,12 +289,23 @@ impl Canvas {
                 (p0, p1) = (p1, p0);
             }
             let xs = self.interpolate(p0.y, p0.x, p1.y, p1.x);
+            let zs = self.interpolate(p0.y, p0.z, p1.y, p1.z);
             for y in (p0.y as i32)..=(p1.y as i32) {
-                self.put_pixel(
-                    &Vec3::new(xs[(y - p0.y as i32) as usize], y as f32, 1.0),
-                    color,
-                );
+                let idx = (y - p0.y as i32) as usize;
+                self.put_pixel(&Vec3::new(xs[idx], y as f32, zs[idx]), color);
             }
         }
     }
 }
+
+/// Value at column `x` of a row running from `l` at `x_l` to `r` at `x_r`.
+///
+/// Steps the same way as [`Canvas::interpolate`] but also continues past
+/// the ends of the row.
+fn value_on_row(x_l: f32, l: f32, x_r: f32, r: f32, x: i32) -> f32 {
+    if x_l == x_r {
+        l
+    } else {
+        l + (x - x_l as i32) as f32 * (r - l) / (x_r - x_l)
+    }
+}
@@ -1,36 +1,116 @@
-/*
-use super::viewport::Plane;
-use crate::{color, models::triangle::Triangle};
-
-pub fn clip_triangle(triangle: Triangle, plane: Plane) -> &[Triangle] {
-    let d0 = plane.signed_distance(triangle.vertices[0]);
-    let d1 = plane.signed_distance(triangle.vertices[1]);
-    let d2 = plane.signed_distance(triangle.vertices[2]);
-
-    if d0.is_sign_positive() && d1.is_sign_positive() && d2.is_sign_positive() {
-        return &[triangle];
-    } else if d0.is_sign_negative() && d1.is_sign_negative() && d2.is_sign_negative() {
-        return &[];
-    } else if d0.is_sign_positive() ^ d1.is_sign_positive() ^ d2.is_sign_positive() {
-        let mut a: Vec4;
-        let mut b: Vec4;
-        let mut c: Vec4;
-        if d0.is_sign_positive() {
-            a = triangle.vertices[0];
-            b = triangle.vertices[1];
-            c = triangle.vertices[2];
-        } else if d1.is_sign_positive() {
-            a = triangle.vertices[1];
-            b = triangle.vertices[0];
-            c = triangle.vertices[2]
-        } else {
-            a = triangle.vertices[2];
-            b = triangle.vertices[1];
-            c = triangle.vertices[0];
+use crate::models::triangle::Triangle;
+use crate::models::vertex::Vertex;
+use crate::rendering::viewport::Plane;
+use nalgebra_glm::Vec4;
+
+/// A vertex that can be split where a triangle edge crosses a plane
+pub trait ClipVertex: Clone {
+    fn position(&self) -> &Vec4;
+
+    /// Blends every attribute towards `other` by `t` (0 is self, 1 is other)
+    fn lerp(&self, other: &Self, t: f32) -> Self;
+}
+
+impl ClipVertex for Vec4 {
+    fn position(&self) -> &Vec4 {
+        self
+    }
+
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + t * (other - self)
+    }
+}
+
+impl ClipVertex for Vertex {
+    fn position(&self) -> &Vec4 {
+        &self.position
+    }
+
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Vertex::lerp(self, other, t)
+    }
+}
+
+/// Clips a triangle against a single plane.
+///
+/// Returns zero, one or two triangles that lie on the positive side of the
+/// plane. Vertices created where edges cross the plane are appended to
+/// `vertices` and referenced by index like every other vertex. The winding
+/// order of the original triangle is preserved.
+pub fn clip_triangle<V: ClipVertex>(
+    triangle: &Triangle,
+    plane: &Plane,
+    vertices: &mut Vec<V>,
+) -> Vec<Triangle> {
+    let idx = triangle.vertices;
+    let inside = idx.map(|i| plane.signed_distance(vertices[i as usize].position()) >= 0.0);
+
+    match inside.iter().filter(|&&is_inside| is_inside).count() {
+        3 => vec![triangle.clone()],
+        0 => vec![],
+        1 => {
+            // Rotate so that A is the only vertex inside while keeping the winding
+            let i = inside.iter().position(|&is_inside| is_inside).unwrap();
+            let (a, b, c) = (idx[i], idx[(i + 1) % 3], idx[(i + 2) % 3]);
+
+            let bp = push_intersection(plane, a, b, vertices);
+            let cp = push_intersection(plane, a, c, vertices);
+
+            vec![Triangle {
+                vertices: [a, bp, cp],
+                ..triangle.clone()
+            }]
+        }
+        _ => {
+            // Rotate so that C is the only vertex outside while keeping the winding
+            let i = inside.iter().position(|&is_inside| !is_inside).unwrap();
+            let (c, a, b) = (idx[i], idx[(i + 1) % 3], idx[(i + 2) % 3]);
+
+            let ap = push_intersection(plane, a, c, vertices);
+            let bp = push_intersection(plane, b, c, vertices);
+
+            vec![
+                Triangle {
+                    vertices: [a, b, bp],
+                    ..triangle.clone()
+                },
+                Triangle {
+                    vertices: [a, bp, ap],
+                    ..triangle.clone()
+                },
+            ]
         }
-        let bp = plane.intersection(a, b);
-        let cp = plane.intersection(a, c);
-        todo!("Need triangle object to contain vertices");
     }
 }
-*/
+
+/// Clips every triangle against every plane in turn.
+pub fn clip_triangles<V: ClipVertex>(
+    triangles: &[Triangle],
+    planes: &[Plane],
+    vertices: &mut Vec<V>,
+) -> Vec<Triangle> {
+    let mut clipped = triangles.to_vec();
+    for plane in planes {
+        clipped = clipped
+            .iter()
+            .flat_map(|triangle| clip_triangle(triangle, plane, vertices))
+            .collect();
+    }
+
+    clipped
+}
+
+/// Adds the point where the edge between two vertices crosses the plane and
+/// returns its index
+fn push_intersection<V: ClipVertex>(
+    plane: &Plane,
+    from: i32,
+    to: i32,
+    vertices: &mut Vec<V>,
+) -> i32 {
+    let (from, to) = (&vertices[from as usize], &vertices[to as usize]);
+    let t = plane.intersection_factor(from.position(), to.position());
+    vertices.push(from.lerp(to, t));
+
+    (vertices.len() - 1) as i32
+}
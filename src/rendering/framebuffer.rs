@@ -0,0 +1,118 @@
+use crate::color::Color;
+
+/// How incoming depth is compared against the depth buffer.
+///
+/// Depth is stored as 1/z so it can be interpolated linearly in screen space.
+/// A larger value is closer to the camera.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DepthTest {
+    /// Pass when strictly closer than what is stored
+    Less,
+    /// Pass when closer than or as close as what is stored
+    LessEqual,
+    /// Always pass and overwrite the stored depth
+    Always,
+    /// Always pass and leave the stored depth alone
+    Off,
+}
+
+/// An in-memory grid of packed `0x00RRGGBB` pixels and their depths.
+///
+/// Everything a [`Canvas`](crate::rendering::canvas::Canvas) draws ends up
+/// here first. A [`Backend`](crate::rendering::backend::Backend) decides what
+/// to do with it afterwards (show it in a window, or nothing at all).
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<u32>,
+    depth: Vec<f32>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height],
+            depth: vec![0.0; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Row-major packed pixels, top left first
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
+    }
+
+    /// Fills every pixel with given color.
+    pub fn clear(&mut self, color: Color) {
+        self.pixels.fill(color.into());
+    }
+
+    /// Resets every depth to infinitely far away (1/z = 0).
+    pub fn clear_depth(&mut self) {
+        self.depth.fill(0.0);
+    }
+
+    /// Depth (1/z) stored at given "Canvas Space" coordinates.
+    pub fn get_depth(&self, x: i32, y: i32) -> Option<f32> {
+        self.index(x, y).map(|idx| self.depth[idx])
+    }
+
+    /// Tests given 1/z against the depth buffer, storing it if the test passes.
+    ///
+    /// Coordinates outside of the buffer always fail.
+    pub fn test_depth(&mut self, x: i32, y: i32, inv_z: f32, test: DepthTest) -> bool {
+        let passed = self.passes_depth(x, y, inv_z, test);
+        if passed && test != DepthTest::Off {
+            if let Some(idx) = self.index(x, y) {
+                self.depth[idx] = inv_z;
+            }
+        }
+
+        passed
+    }
+
+    /// Tests given 1/z against the depth buffer without storing it.
+    ///
+    /// Coordinates outside of the buffer always fail.
+    pub fn passes_depth(&self, x: i32, y: i32, inv_z: f32, test: DepthTest) -> bool {
+        match self.index(x, y) {
+            None => false,
+            Some(idx) => match test {
+                DepthTest::Less => inv_z > self.depth[idx],
+                DepthTest::LessEqual => inv_z >= self.depth[idx],
+                DepthTest::Always | DepthTest::Off => true,
+            },
+        }
+    }
+
+    /// Sets the pixel at given "Canvas Space" coordinates.
+    ///
+    /// Coordinates outside of the buffer are silently ignored.
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: Color) {
+        if let Some(idx) = self.index(x, y) {
+            self.pixels[idx] = color.into();
+        }
+    }
+
+    /// Gets the pixel at given "Canvas Space" coordinates.
+    pub fn get_pixel(&self, x: i32, y: i32) -> Option<Color> {
+        self.index(x, y).map(|idx| Color::from(self.pixels[idx]))
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            None
+        } else {
+            Some(y as usize * self.width + x as usize)
+        }
+    }
+}
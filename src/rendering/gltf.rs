@@ -0,0 +1,1050 @@
+//! glTF 2.0 scenes, as `.gltf` JSON with external or embedded buffers, or as
+//! binary `.glb`.
+//!
+//! Meshes become models shared between the instances using them and every
+//! node with a mesh becomes an instance. Base color factors and textures
+//! become materials. Cameras, lights, skins and animations are ignored.
+//!
+//! glTF is right-handed with counter-clockwise front faces, so z is mirrored
+//! and faces are reversed while loading, like for OBJ.
+
+use crate::color;
+use crate::color::Color;
+use crate::image;
+use crate::models::material::Material;
+use crate::models::model::{Instance, Model};
+use crate::models::triangle::Triangle;
+use crate::rendering::scene::Scene;
+use crate::rendering::texture::{FilterMode, Texture, WrapMode};
+use nalgebra_glm::{quat, quat_normalize, quat_to_mat4, Mat4, Vec2, Vec3, Vec4};
+use serde_json::Value;
+use std::cell::RefCell;
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_JSON: u32 = 0x4e4f_534a;
+const GLB_BIN: u32 = 0x004e_4942;
+
+/// Largest accessor we agree to read, in elements
+const MAX_ELEMENTS: usize = 1 << 26;
+
+#[derive(Debug)]
+pub enum GltfError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// The file breaks the rules of glTF at given location, e.g. `meshes[2]`
+    Malformed {
+        location: String,
+        reason: String,
+    },
+    /// The file is valid but uses a feature we cannot import
+    Unsupported {
+        location: String,
+        feature: String,
+    },
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GltfError::Io(error) => write!(f, "{error}"),
+            GltfError::Json(error) => write!(f, "invalid JSON: {error}"),
+            GltfError::Malformed { location, reason } => write!(f, "{location}: {reason}"),
+            GltfError::Unsupported { location, feature } => {
+                write!(f, "{location}: unsupported {feature}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for GltfError {}
+
+impl From<std::io::Error> for GltfError {
+    fn from(error: std::io::Error) -> Self {
+        GltfError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for GltfError {
+    fn from(error: serde_json::Error) -> Self {
+        GltfError::Json(error)
+    }
+}
+
+fn malformed<T>(location: &str, reason: impl Into<String>) -> Result<T, GltfError> {
+    Err(GltfError::Malformed {
+        location: location.to_string(),
+        reason: reason.into(),
+    })
+}
+
+fn unsupported<T>(location: &str, feature: impl Into<String>) -> Result<T, GltfError> {
+    Err(GltfError::Unsupported {
+        location: location.to_string(),
+        feature: feature.into(),
+    })
+}
+
+/// Reads and imports a `.gltf` or `.glb` file, loading external buffers and
+/// images relative to it
+pub fn load(path: impl AsRef<Path>) -> Result<Scene, GltfError> {
+    let path = path.as_ref();
+    decode(
+        &std::fs::read(path)?,
+        path.parent().unwrap_or(Path::new("")),
+    )
+}
+
+/// Imports glTF JSON or a binary GLB, loading external files relative to `base`
+pub fn decode(bytes: &[u8], base: &Path) -> Result<Scene, GltfError> {
+    let (json, bin) = match bytes.starts_with(GLB_MAGIC) {
+        true => split_glb(bytes)?,
+        false => (bytes, None),
+    };
+    let root: Value = serde_json::from_slice(json)?;
+
+    let version = root["asset"]["version"].as_str().unwrap_or("");
+    if !version.starts_with("2.") {
+        return unsupported("asset.version", format!("glTF version {version:?}"));
+    }
+
+    let mut importer = Importer {
+        root: &root,
+        base,
+        buffers: vec![],
+        models: vec![None; array(&root, "meshes").len()],
+        materials: vec![None; array(&root, "materials").len()],
+        visited: vec![false; array(&root, "nodes").len()],
+    };
+    for (i, buffer) in array(&root, "buffers").iter().enumerate() {
+        let location = format!("buffers[{i}]");
+        let data = match buffer["uri"].as_str() {
+            Some(uri) => importer.read_uri(uri, &location)?,
+            None => match (i, bin) {
+                (0, Some(bin)) => bin.to_vec(),
+                _ => return malformed(&location, "buffer has no uri and no GLB chunk"),
+            },
+        };
+        let len = usize_field(buffer, "byteLength", &location)?.unwrap_or(0);
+        if data.len() < len {
+            return malformed(&location, format!("holds {} of {len} bytes", data.len()));
+        }
+        importer.buffers.push(data);
+    }
+
+    // Nodes of the default scene, or every node that isn't a child otherwise
+    let roots: Vec<usize> = match root["scene"]
+        .as_u64()
+        .or(array(&root, "scenes").first().map(|_| 0))
+    {
+        Some(scene) => {
+            let location = format!("scenes[{scene}]");
+            let Some(scene) = array(&root, "scenes").get(scene as usize) else {
+                return malformed("scene", format!("{location} does not exist"));
+            };
+            indices(scene, "nodes", &location)?
+        }
+        None => {
+            let nodes = array(&root, "nodes");
+            let mut is_child = vec![false; nodes.len()];
+            for (i, node) in nodes.iter().enumerate() {
+                for child in indices(node, "children", &format!("nodes[{i}]"))? {
+                    if let Some(flag) = is_child.get_mut(child) {
+                        *flag = true;
+                    }
+                }
+            }
+            (0..nodes.len()).filter(|&i| !is_child[i]).collect()
+        }
+    };
+
+    let mut scene = Scene::new();
+    for node in roots {
+        importer.add_node(&mut scene, node, &Mat4::identity())?;
+    }
+    Ok(scene)
+}
+
+/// Splits a GLB file into its JSON and optional binary chunk
+fn split_glb(bytes: &[u8]) -> Result<(&[u8], Option<&[u8]>), GltfError> {
+    let u32_at = |offset: usize| {
+        bytes
+            .get(offset..offset + 4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+    };
+    if u32_at(4) != Some(2) {
+        return unsupported("GLB header", "container version");
+    }
+    let len = u32_at(8).unwrap_or(0) as usize;
+    if len > bytes.len() {
+        return malformed(
+            "GLB header",
+            format!("length {len} but file is {}", bytes.len()),
+        );
+    }
+
+    let mut chunks = vec![];
+    let mut offset = 12;
+    while offset < len {
+        let (Some(chunk_len), Some(kind)) = (u32_at(offset), u32_at(offset + 4)) else {
+            return malformed(&format!("GLB chunk at byte {offset}"), "truncated header");
+        };
+        let start = offset + 8;
+        let end = start + chunk_len as usize;
+        if end > len {
+            return malformed(&format!("GLB chunk at byte {offset}"), "runs past the end");
+        }
+        chunks.push((kind, &bytes[start..end]));
+        // Chunks are padded to 4 bytes
+        offset = end.next_multiple_of(4);
+    }
+
+    match chunks.as_slice() {
+        [(GLB_JSON, json), rest @ ..] => {
+            let bin = rest
+                .iter()
+                .find(|(kind, _)| *kind == GLB_BIN)
+                .map(|(_, data)| *data);
+            Ok((json, bin))
+        }
+        _ => malformed("GLB", "first chunk is not JSON"),
+    }
+}
+
+/// Everything needed while walking the document, along with what has been
+/// imported so far so it can be shared
+struct Importer<'a> {
+    root: &'a Value,
+    base: &'a Path,
+    buffers: Vec<Vec<u8>>,
+    models: Vec<Option<Rc<Model>>>,
+    materials: Vec<Option<Rc<Material>>>,
+    /// Nodes added so far. Nodes form disjoint trees, so none is reached twice.
+    visited: Vec<bool>,
+}
+
+impl Importer<'_> {
+    /// Adds a node and its children below given parent transform
+    fn add_node(
+        &mut self,
+        scene: &mut Scene,
+        index: usize,
+        parent: &Mat4,
+    ) -> Result<(), GltfError> {
+        let location = format!("nodes[{index}]");
+        let Some(node) = array(self.root, "nodes").get(index) else {
+            return malformed(&location, "node does not exist");
+        };
+        // Also stops cycles, which would reach a node a second time
+        if std::mem::replace(&mut self.visited[index], true) {
+            return malformed(&location, "node has more than one parent");
+        }
+
+        // Mirroring z on both sides of a transform keeps it working in our coordinates
+        let mirror = Mat4::new_nonuniform_scaling(&Vec3::new(1.0, 1.0, -1.0));
+        let mut translation = Vec4::new(0.0, 0.0, 0.0, 0.0);
+        let mut rotation = quat(0.0, 0.0, 0.0, 1.0);
+        let mut scale = Vec4::new(1.0, 1.0, 1.0, 0.0);
+        let mut base = *parent;
+        if let Some(matrix) = node.get("matrix") {
+            let values: [f32; 16] = floats(matrix, &format!("{location}.matrix"))?;
+            base = parent * mirror * Mat4::from_column_slice(&values) * mirror;
+        } else {
+            if let Some(value) = node.get("translation") {
+                let [x, y, z] = floats(value, &format!("{location}.translation"))?;
+                translation = Vec4::new(x, y, -z, 0.0);
+            }
+            if let Some(value) = node.get("rotation") {
+                let [x, y, z, w] = floats(value, &format!("{location}.rotation"))?;
+                rotation = quat_normalize(&quat(-x, -y, z, w));
+            }
+            if let Some(value) = node.get("scale") {
+                let [x, y, z] = floats(value, &format!("{location}.scale"))?;
+                scale = Vec4::new(x, y, z, 0.0);
+            }
+        }
+
+        let transform = base
+            * Mat4::new_translation(&translation.xyz())
+            * quat_to_mat4(&rotation)
+            * Mat4::new_nonuniform_scaling(&scale.xyz());
+
+        if let Some(mesh) = usize_field(node, "mesh", &location)? {
+            let mut instance =
+                Instance::new(self.model(mesh)?, &scale, &Vec4::zeros(), &translation);
+            instance.set_rotation_quaternion(&rotation);
+            instance.set_parent(&base);
+            scene.add_instance(Rc::new(RefCell::new(instance)));
+        }
+        for child in indices(node, "children", &location)? {
+            self.add_node(scene, child, &transform)?;
+        }
+
+        Ok(())
+    }
+
+    /// The model of a mesh, imported the first time it is asked for
+    fn model(&mut self, index: usize) -> Result<Rc<Model>, GltfError> {
+        if let Some(Some(model)) = self.models.get(index) {
+            return Ok(Rc::clone(model));
+        }
+        let location = format!("meshes[{index}]");
+        let Some(mesh) = array(self.root, "meshes").get(index) else {
+            return malformed(&location, "mesh does not exist");
+        };
+
+        let mut positions: Vec<Vec4> = vec![];
+        let mut normals: Option<Vec<Vec3>> = Some(vec![]);
+        let mut uvs: Option<Vec<Vec2>> = Some(vec![]);
+        let mut colors: Option<Vec<Color>> = Some(vec![]);
+        let mut triangles: Vec<Triangle> = vec![];
+        // The primitive every triangle comes from, to point validation errors at
+        let mut primitives: Vec<usize> = vec![];
+
+        for (p, primitive) in array(mesh, "primitives").iter().enumerate() {
+            let location = format!("{location}.primitives[{p}]");
+            let mode = usize_field(primitive, "mode", &location)?.unwrap_or(4);
+            if !(4..=6).contains(&mode) {
+                // Points and lines have no surface to draw
+                continue;
+            }
+            let attributes = &primitive["attributes"];
+            let Some(position) = usize_field(attributes, "POSITION", &location)? else {
+                return malformed(&location, "primitive has no POSITION");
+            };
+            let first = positions.len();
+            let (values, _) = self.accessor(position, &[3])?;
+            let count = values.len() / 3;
+            positions.extend(
+                values
+                    .chunks(3)
+                    .map(|xyz| Vec4::new(xyz[0], xyz[1], -xyz[2], 1.0)),
+            );
+            if positions.len() > i32::MAX as usize {
+                return malformed(&location, "mesh has too many vertices");
+            }
+
+            // Attributes are only kept if every primitive has them
+            let attribute = |importer: &Self, name: &str, sizes: &[usize]| match usize_field(
+                attributes, name, &location,
+            )? {
+                Some(index) => importer.accessor(index, sizes).map(Some),
+                None => Ok(None),
+            };
+            normals = match (normals, attribute(self, "NORMAL", &[3])?) {
+                (Some(mut normals), Some((values, _))) if values.len() == count * 3 => {
+                    normals.extend(values.chunks(3).map(|n| Vec3::new(n[0], n[1], -n[2])));
+                    Some(normals)
+                }
+                _ => None,
+            };
+            uvs = match (uvs, attribute(self, "TEXCOORD_0", &[2])?) {
+                (Some(mut uvs), Some((values, _))) if values.len() == count * 2 => {
+                    uvs.extend(values.chunks(2).map(|uv| Vec2::new(uv[0], uv[1])));
+                    Some(uvs)
+                }
+                _ => None,
+            };
+            colors = match (colors, attribute(self, "COLOR_0", &[3, 4])?) {
+                (Some(mut colors), Some((values, size))) if values.len() == count * size => {
+                    colors.extend(values.chunks(size).map(|rgb| {
+                        Color::from(Vec3::new(rgb[0], rgb[1], rgb[2]).map(linear_to_srgb))
+                    }));
+                    Some(colors)
+                }
+                _ => None,
+            };
+
+            let corners: Vec<usize> = match usize_field(primitive, "indices", &location)? {
+                Some(index) => self.index_accessor(index)?,
+                None => (0..count).collect(),
+            };
+            if let Some(&corner) = corners.iter().find(|&&corner| corner >= count) {
+                return malformed(&location, format!("index {corner} of {count} vertices"));
+            }
+
+            let material = match usize_field(primitive, "material", &location)? {
+                Some(index) => Some(self.material(index)?),
+                None => None,
+            };
+            let faces: Vec<[usize; 3]> = match mode {
+                4 => corners
+                    .chunks_exact(3)
+                    .map(|c| [c[0], c[1], c[2]])
+                    .collect(),
+                // Strips are often stitched together with triangles that
+                // repeat a corner, which have nothing to draw
+                5 => (0..corners.len().saturating_sub(2))
+                    .map(|i| match i % 2 {
+                        0 => [corners[i], corners[i + 1], corners[i + 2]],
+                        _ => [corners[i + 1], corners[i], corners[i + 2]],
+                    })
+                    .filter(|&[a, b, c]| a != b && b != c && c != a)
+                    .collect(),
+                _ => (1..corners.len().saturating_sub(1))
+                    .map(|i| [corners[0], corners[i], corners[i + 1]])
+                    .collect(),
+            };
+            for [a, b, c] in faces {
+                let [a, b, c] = [a, c, b].map(|corner| (first + corner) as i32);
+                let triangle = Triangle::new(a, b, c, color::WHITE);
+                triangles.push(match &material {
+                    Some(material) => triangle.with_material(Rc::clone(material)),
+                    None => triangle,
+                });
+                primitives.push(p);
+            }
+        }
+
+        let has_vertices = !positions.is_empty();
+        let (mut model, _) =
+            Model::try_new_lenient(positions, triangles).map_err(|error| GltfError::Malformed {
+                location: format!(
+                    "{location}.primitives[{}]",
+                    primitives[error.get_triangle()]
+                ),
+                reason: error.to_string(),
+            })?;
+        if let Some(normals) = normals.filter(|_| has_vertices) {
+            model = model.with_normals(
+                normals
+                    .iter()
+                    .map(|normal| normal.try_normalize(f32::EPSILON).unwrap_or_default())
+                    .collect(),
+            );
+        }
+        if let Some(uvs) = uvs.filter(|_| has_vertices) {
+            model = model.with_uvs(uvs);
+        }
+        if let Some(colors) = colors.filter(|_| has_vertices) {
+            model = model.with_colors(colors);
+        }
+
+        let model = Rc::new(model);
+        self.models[index] = Some(Rc::clone(&model));
+        Ok(model)
+    }
+
+    /// The material at given index, imported the first time it is asked for
+    fn material(&mut self, index: usize) -> Result<Rc<Material>, GltfError> {
+        if let Some(Some(material)) = self.materials.get(index) {
+            return Ok(Rc::clone(material));
+        }
+        let location = format!("materials[{index}]");
+        let Some(value) = array(self.root, "materials").get(index) else {
+            return malformed(&location, "material does not exist");
+        };
+
+        let name = match value["name"].as_str() {
+            Some(name) => name.to_string(),
+            None => format!("material {index}"),
+        };
+        let mut material = Material::new(&name);
+        let pbr = &value["pbrMetallicRoughness"];
+        if let Some(factor) = pbr.get("baseColorFactor") {
+            let rgba: [f32; 4] = floats(factor, &format!("{location}.baseColorFactor"))?;
+            material.diffuse =
+                Color::from(Vec3::new(rgba[0], rgba[1], rgba[2]).map(linear_to_srgb));
+            material.opacity = rgba[3];
+        }
+        if let Some(texture) = usize_field(&pbr["baseColorTexture"], "index", &location)? {
+            material.diffuse_map = Some(Rc::new(self.texture(texture, material.diffuse)?));
+        }
+
+        let material = Rc::new(material);
+        self.materials[index] = Some(Rc::clone(&material));
+        Ok(material)
+    }
+
+    /// The texture at given index, multiplied by `tint`
+    fn texture(&self, index: usize, tint: Color) -> Result<Texture, GltfError> {
+        let location = format!("textures[{index}]");
+        let Some(texture) = array(self.root, "textures").get(index) else {
+            return malformed(&location, "texture does not exist");
+        };
+        let Some(source) = usize_field(texture, "source", &location)? else {
+            return unsupported(&location, "texture without a source image");
+        };
+
+        let image_location = format!("images[{source}]");
+        let Some(image) = array(self.root, "images").get(source) else {
+            return malformed(&image_location, "image does not exist");
+        };
+        let bytes = match (
+            image["uri"].as_str(),
+            usize_field(image, "bufferView", &image_location)?,
+        ) {
+            (Some(uri), _) => self.read_uri(uri, &image_location)?,
+            (None, Some(view)) => self.buffer_view(view)?.to_vec(),
+            (None, None) => return malformed(&image_location, "image has no uri or bufferView"),
+        };
+        let decoded = image::decode(&bytes).map_err(|error| GltfError::Malformed {
+            location: image_location,
+            reason: error.to_string(),
+        })?;
+
+        let wrap = match usize_field(texture, "sampler", &location)? {
+            Some(sampler) => match array(self.root, "samplers")
+                .get(sampler)
+                .and_then(|sampler| sampler["wrapS"].as_u64())
+            {
+                Some(33071) => WrapMode::Clamp,
+                Some(33648) => WrapMode::Mirror,
+                _ => WrapMode::Repeat,
+            },
+            None => WrapMode::Repeat,
+        };
+        Ok(decoded
+            .with_tint(tint)
+            .into_texture()
+            .with_wrap(wrap)
+            .with_mipmaps()
+            .with_filter(FilterMode::Trilinear))
+    }
+
+    /// Loads a `data:` URI or a file relative to the document
+    fn read_uri(&self, uri: &str, location: &str) -> Result<Vec<u8>, GltfError> {
+        if let Some(data) = uri.strip_prefix("data:") {
+            let Some((_, encoded)) = data.split_once(";base64,") else {
+                return unsupported(location, "data URI that is not base64");
+            };
+            return match base64_decode(encoded) {
+                Some(bytes) => Ok(bytes),
+                None => malformed(location, "invalid base64 in data URI"),
+            };
+        }
+        let path = self.base.join(percent_decode(uri));
+        std::fs::read(&path).map_err(|error| GltfError::Malformed {
+            location: location.to_string(),
+            reason: format!("{}: {error}", path.display()),
+        })
+    }
+
+    /// Bytes of a buffer view, checked against its buffer
+    fn buffer_view(&self, index: usize) -> Result<&[u8], GltfError> {
+        let location = format!("bufferViews[{index}]");
+        let Some(view) = array(self.root, "bufferViews").get(index) else {
+            return malformed(&location, "buffer view does not exist");
+        };
+        let Some(buffer) = usize_field(view, "buffer", &location)? else {
+            return malformed(&location, "buffer view has no buffer");
+        };
+        let Some(data) = self.buffers.get(buffer) else {
+            return malformed(&location, format!("buffer {buffer} does not exist"));
+        };
+        let offset = usize_field(view, "byteOffset", &location)?.unwrap_or(0);
+        let len = usize_field(view, "byteLength", &location)?.unwrap_or(0);
+        match data.get(offset..offset.saturating_add(len)) {
+            Some(bytes) => Ok(bytes),
+            None => malformed(&location, "runs past the end of its buffer"),
+        }
+    }
+
+    /// Finds an accessor and checks that it fits in its buffer view
+    fn accessor_view(&self, index: usize, sizes: &[usize]) -> Result<AccessorView<'_>, GltfError> {
+        let location = format!("accessors[{index}]");
+        let Some(accessor) = array(self.root, "accessors").get(index) else {
+            return malformed(&location, "accessor does not exist");
+        };
+        if accessor.get("sparse").is_some() {
+            return unsupported(&location, "sparse accessor");
+        }
+
+        let size = match accessor["type"].as_str() {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            kind => return unsupported(&location, format!("accessor type {kind:?}")),
+        };
+        if !sizes.contains(&size) {
+            return malformed(
+                &location,
+                format!("{size} components where {sizes:?} are expected"),
+            );
+        }
+        let component = usize_field(accessor, "componentType", &location)?.unwrap_or(0);
+        let component_len = match component {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            _ => return unsupported(&location, format!("component type {component}")),
+        };
+        let normalized = accessor["normalized"].as_bool().unwrap_or(false);
+        let count = usize_field(accessor, "count", &location)?.unwrap_or(0);
+        if count > MAX_ELEMENTS {
+            return malformed(&location, format!("{count} elements is too many"));
+        }
+
+        let mut view = AccessorView {
+            location,
+            bytes: None,
+            offset: 0,
+            stride: 0,
+            count,
+            size,
+            component,
+            component_len,
+            normalized,
+        };
+        // Accessors without a buffer view are all zeros
+        let Some(buffer_view) = usize_field(accessor, "bufferView", &view.location)? else {
+            return Ok(view);
+        };
+        let bytes = self.buffer_view(buffer_view)?;
+        let element_len = size * component_len;
+        view.stride = match usize_field(
+            &array(self.root, "bufferViews")[buffer_view],
+            "byteStride",
+            &view.location,
+        )? {
+            Some(stride) if stride >= element_len => stride,
+            _ => element_len,
+        };
+        view.offset = usize_field(accessor, "byteOffset", &view.location)?.unwrap_or(0);
+        let end = match count {
+            0 => Some(view.offset),
+            _ => (count - 1)
+                .checked_mul(view.stride)
+                .and_then(|len| len.checked_add(element_len))
+                .and_then(|len| len.checked_add(view.offset)),
+        };
+        if end.is_none_or(|end| end > bytes.len()) {
+            return malformed(&view.location, "runs past the end of its buffer view");
+        }
+        view.bytes = Some(bytes);
+        Ok(view)
+    }
+
+    /// Reads an accessor as floats, returning them along with the number of
+    /// components per element. Normalized integers are scaled to 0..1 (or -1..1).
+    fn accessor(&self, index: usize, sizes: &[usize]) -> Result<(Vec<f32>, usize), GltfError> {
+        let view = self.accessor_view(index, sizes)?;
+        let values = view
+            .components()
+            .map(|data| match view.component {
+                5120 => {
+                    let value = data[0] as i8 as f32;
+                    if view.normalized {
+                        (value / 127.0).max(-1.0)
+                    } else {
+                        value
+                    }
+                }
+                5121 => {
+                    let value = data[0] as f32;
+                    if view.normalized {
+                        value / 255.0
+                    } else {
+                        value
+                    }
+                }
+                5122 => {
+                    let value = i16::from_le_bytes([data[0], data[1]]) as f32;
+                    if view.normalized {
+                        (value / 32767.0).max(-1.0)
+                    } else {
+                        value
+                    }
+                }
+                5123 => {
+                    let value = u16::from_le_bytes([data[0], data[1]]) as f32;
+                    if view.normalized {
+                        value / 65535.0
+                    } else {
+                        value
+                    }
+                }
+                5125 => u32::from_le_bytes(data.try_into().unwrap()) as f32,
+                _ => f32::from_le_bytes(data.try_into().unwrap()),
+            })
+            .collect();
+
+        Ok((values, view.size))
+    }
+
+    /// Reads an accessor of vertex indices, which must be unsigned integers
+    fn index_accessor(&self, index: usize) -> Result<Vec<usize>, GltfError> {
+        let view = self.accessor_view(index, &[1])?;
+        if !matches!(view.component, 5121 | 5123 | 5125) || view.normalized {
+            return malformed(&view.location, "indices are not unsigned integers");
+        }
+        Ok(view
+            .components()
+            .map(|data| match data {
+                [value] => *value as usize,
+                [low, high] => u16::from_le_bytes([*low, *high]) as usize,
+                _ => u32::from_le_bytes(data.try_into().unwrap()) as usize,
+            })
+            .collect())
+    }
+}
+
+/// An accessor found to fit in its buffer view
+struct AccessorView<'a> {
+    location: String,
+    /// `None` when there is no buffer view and every component is zero
+    bytes: Option<&'a [u8]>,
+    offset: usize,
+    stride: usize,
+    count: usize,
+    /// Components per element
+    size: usize,
+    component: usize,
+    component_len: usize,
+    normalized: bool,
+}
+
+impl AccessorView<'_> {
+    /// Bytes of every component of every element in order
+    fn components(&self) -> impl Iterator<Item = &[u8]> {
+        const ZEROS: [u8; 4] = [0; 4];
+        (0..self.count).flat_map(move |element| {
+            (0..self.size).map(move |k| match self.bytes {
+                Some(bytes) => {
+                    let start = self.offset + element * self.stride + k * self.component_len;
+                    &bytes[start..start + self.component_len]
+                }
+                None => &ZEROS[..self.component_len],
+            })
+        })
+    }
+}
+
+/// Elements of an array field, empty if it is missing
+fn array<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value[key].as_array().map_or(&[], Vec::as_slice)
+}
+
+/// An optional non-negative integer field
+fn usize_field(value: &Value, key: &str, location: &str) -> Result<Option<usize>, GltfError> {
+    match value.get(key) {
+        None => Ok(None),
+        Some(field) => match field.as_u64() {
+            Some(number) => Ok(Some(number as usize)),
+            None => malformed(location, format!("{key} is not a non-negative integer")),
+        },
+    }
+}
+
+/// An optional array of indices
+fn indices(value: &Value, key: &str, location: &str) -> Result<Vec<usize>, GltfError> {
+    array(value, key)
+        .iter()
+        .map(|index| match index.as_u64() {
+            Some(index) => Ok(index as usize),
+            None => malformed(
+                location,
+                format!("{key} holds something other than indices"),
+            ),
+        })
+        .collect()
+}
+
+/// An array of exactly `N` numbers
+fn floats<const N: usize>(value: &Value, location: &str) -> Result<[f32; N], GltfError> {
+    let numbers: Option<Vec<f32>> = value.as_array().and_then(|values| {
+        values
+            .iter()
+            .map(|value| value.as_f64().map(|value| value as f32))
+            .collect()
+    });
+    match numbers.and_then(|numbers| numbers.try_into().ok()) {
+        Some(numbers) => Ok(numbers),
+        None => malformed(location, format!("expected {N} numbers")),
+    }
+}
+
+/// glTF colors are linear, ours are meant for the screen
+fn linear_to_srgb(value: f32) -> f32 {
+    let value = value.clamp(0.0, 1.0);
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Decodes standard base64, ignoring padding
+fn base64_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(encoded.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+    for byte in encoded.bytes().take_while(|&byte| byte != b'=') {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | value as u32;
+        bit_count += 6;
+        if bit_count >= 8 {
+            bit_count -= 8;
+            bytes.push((bits >> bit_count) as u8);
+        }
+    }
+
+    Some(bytes)
+}
+
+/// Turns `%20` and friends in relative URIs back into characters
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::{ppm, Image};
+    use serde_json::json;
+
+    /// Packs a document and its binary chunk into a GLB file
+    fn glb(document: &Value, bin: &[u8]) -> Vec<u8> {
+        let mut json = serde_json::to_vec(document).unwrap();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let mut bin = bin.to_vec();
+        bin.resize(bin.len().next_multiple_of(4), 0);
+
+        let mut bytes = GLB_MAGIC.to_vec();
+        bytes.extend(2u32.to_le_bytes());
+        bytes.extend(((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+        for (kind, data) in [(GLB_JSON, &json), (GLB_BIN, &bin)] {
+            bytes.extend((data.len() as u32).to_le_bytes());
+            bytes.extend(kind.to_le_bytes());
+            bytes.extend(data);
+        }
+        bytes
+    }
+
+    /// A mesh whose positions and normals come from the start of the binary
+    /// chunk, followed by u32 indices
+    fn mesh(positions: &[[f32; 3]], normals: &[[f32; 3]], indices: &[u32]) -> (Value, Vec<u8>) {
+        let mut bin: Vec<u8> = vec![];
+        for value in positions.iter().chain(normals).flatten() {
+            bin.extend(value.to_le_bytes());
+        }
+        let index_offset = bin.len();
+        for index in indices {
+            bin.extend(index.to_le_bytes());
+        }
+
+        let mut attributes = json!({ "POSITION": 0 });
+        if !normals.is_empty() {
+            attributes["NORMAL"] = json!(1);
+        }
+        let document = json!({
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": bin.len() }],
+            "bufferViews": [{ "buffer": 0, "byteLength": bin.len() }],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": positions.len(), "type": "VEC3" },
+                {
+                    "bufferView": 0,
+                    "byteOffset": positions.len() * 12,
+                    "componentType": 5126,
+                    "count": normals.len(),
+                    "type": "VEC3"
+                },
+                {
+                    "bufferView": 0,
+                    "byteOffset": index_offset,
+                    "componentType": 5125,
+                    "count": indices.len(),
+                    "type": "SCALAR"
+                },
+            ],
+            "meshes": [{ "primitives": [{ "attributes": attributes, "indices": 2 }] }],
+            "nodes": [{ "mesh": 0 }],
+        });
+        (document, bin)
+    }
+
+    const TRIANGLE: [[f32; 3]; 3] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+
+    fn import(document: &Value, bin: &[u8]) -> Result<Scene, GltfError> {
+        decode(&glb(document, bin), Path::new(""))
+    }
+
+    /// Calls `check` with the model of the first instance
+    fn with_model(scene: &Scene, check: impl FnOnce(&Model)) {
+        check(scene.instances[0].borrow().get_model());
+    }
+
+    fn expect_malformed(result: Result<Scene, GltfError>, location: &str) {
+        match result {
+            Err(GltfError::Malformed {
+                location: found, ..
+            }) => assert_eq!(found, location),
+            Err(error) => panic!("expected malformed {location}, got {error}"),
+            Ok(_) => panic!("expected malformed {location}"),
+        }
+    }
+
+    #[test]
+    fn mirrors_z_and_reverses_faces() {
+        let (document, bin) = mesh(&TRIANGLE, &[[0.0, 0.0, 2.0]; 3], &[0, 1, 2]);
+        let scene = import(&document, &bin).unwrap();
+        with_model(&scene, |model| {
+            assert_eq!(model.triangles[0].vertices, [0, 2, 1]);
+            assert!(model.face_normals()[0].z < 0.0);
+            assert_eq!(model.vertices[0].normal, Some(Vec3::new(0.0, 0.0, -1.0)));
+        });
+    }
+
+    #[test]
+    fn zero_normals_stay_zero() {
+        let (document, bin) = mesh(&TRIANGLE, &[[0.0; 3]; 3], &[0, 1, 2]);
+        let scene = import(&document, &bin).unwrap();
+        with_model(&scene, |model| {
+            assert!(model
+                .vertices
+                .iter()
+                .all(|v| v.normal == Some(Vec3::zeros())));
+        });
+    }
+
+    #[test]
+    fn skips_repeated_triangles() {
+        let (document, bin) = mesh(&TRIANGLE, &[], &[0, 1, 2, 1, 2, 0, 0, 0, 1]);
+        let scene = import(&document, &bin).unwrap();
+        with_model(&scene, |model| {
+            assert_eq!(model.triangles.len(), 1);
+            assert_eq!(model.warnings.len(), 1);
+        });
+    }
+
+    #[test]
+    fn indices_are_read_as_integers() {
+        // 2^24 + 1 has no f32 of its own and would read as 2^24
+        let (document, bin) = mesh(&TRIANGLE, &[], &[0, 1, (1 << 24) + 1]);
+        match import(&document, &bin) {
+            Err(GltfError::Malformed { reason, .. }) => {
+                assert_eq!(reason, "index 16777217 of 3 vertices")
+            }
+            _ => panic!("index out of range should be an error"),
+        }
+
+        let (mut document, bin) = mesh(&TRIANGLE, &[], &[0, 1, 2]);
+        document["accessors"][2]["componentType"] = json!(5126);
+        expect_malformed(import(&document, &bin), "accessors[2]");
+    }
+
+    #[test]
+    fn accessors_must_fit_their_buffer_view() {
+        let (document, bin) = mesh(&TRIANGLE, &[], &[0, 1, 2]);
+        for (field, value) in [
+            ("count", json!(5)),
+            ("count", json!(1u64 << 40)),
+            ("byteOffset", json!(u64::MAX - 4)),
+            ("byteOffset", json!(36)),
+        ] {
+            let mut document = document.clone();
+            document["accessors"][0][field] = value;
+            expect_malformed(import(&document, &bin), "accessors[0]");
+        }
+
+        let mut document = document.clone();
+        document["bufferViews"][0]["byteStride"] = json!(usize::MAX / 2);
+        expect_malformed(import(&document, &bin), "accessors[0]");
+    }
+
+    #[test]
+    fn nodes_must_form_trees() {
+        let (mut document, bin) = mesh(&TRIANGLE, &[], &[0, 1, 2]);
+        // Every node lists the next twice, which would be walked 2^27 times
+        let mut nodes: Vec<Value> = (1..28)
+            .map(|child| json!({ "mesh": 0, "children": [child, child] }))
+            .collect();
+        nodes.push(json!({ "mesh": 0 }));
+        document["nodes"] = json!(nodes);
+        document["scenes"] = json!([{ "nodes": [0] }]);
+        // The walk goes down the first children and stops at the last node's second parent
+        expect_malformed(import(&document, &bin), "nodes[27]");
+
+        // A cycle reaches a node a second time too
+        document["nodes"] = json!([{ "children": [1] }, { "children": [0] }]);
+        expect_malformed(import(&document, &bin), "nodes[0]");
+
+        // So does a node shared by two roots of the scene
+        document["nodes"] = json!([{ "children": [2] }, { "children": [2] }, { "mesh": 0 }]);
+        document["scenes"] = json!([{ "nodes": [0, 1] }]);
+        expect_malformed(import(&document, &bin), "nodes[2]");
+    }
+
+    #[test]
+    fn buffer_views_must_fit_their_buffer() {
+        let (mut document, bin) = mesh(&TRIANGLE, &[], &[0, 1, 2]);
+        document["bufferViews"][0]["byteOffset"] = json!(u64::MAX);
+        expect_malformed(import(&document, &bin), "bufferViews[0]");
+    }
+
+    #[test]
+    fn base_color_factor_tints_the_texture() {
+        let image = Image {
+            width: 1,
+            height: 1,
+            pixels: vec![Color::new(200, 100, 50)],
+        };
+        let (mut document, bin) = mesh(&TRIANGLE, &[], &[0, 1, 2]);
+        document["images"] = json!([{
+            "uri": format!("data:image/x-portable-pixmap;base64,{}", encode_base64(&ppm::encode(&image)))
+        }]);
+        document["textures"] = json!([{ "source": 0 }]);
+        document["materials"] = json!([{
+            "pbrMetallicRoughness": {
+                "baseColorFactor": [1.0, 0.0, 1.0, 0.5],
+                "baseColorTexture": { "index": 0 }
+            }
+        }]);
+        document["meshes"][0]["primitives"][0]["material"] = json!(0);
+        let scene = import(&document, &bin).unwrap();
+        with_model(&scene, |model| {
+            let texture = model.triangles[0].texture.as_ref().unwrap();
+            assert_eq!(texture.get_pixel(0, 0), Color::new(200, 0, 50));
+            assert_eq!(model.triangles[0].material.as_ref().unwrap().opacity, 0.5);
+        });
+    }
+
+    /// Standard base64 with padding
+    fn encode_base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text = String::new();
+        for chunk in bytes.chunks(3) {
+            let word = chunk.iter().enumerate().fold(0u32, |word, (i, &byte)| {
+                word | (byte as u32) << (16 - 8 * i)
+            });
+            for i in 0..4 {
+                text.push(match i <= chunk.len() {
+                    true => ALPHABET[(word >> (18 - 6 * i) & 63) as usize] as char,
+                    false => '=',
+                });
+            }
+        }
+        text
+    }
+}
@@ -0,0 +1,164 @@
+use crate::color::Color;
+use nalgebra_glm::{Mat4, Vec3, Vec4};
+
+/// Where light comes from
+#[derive(Copy, Clone, Debug)]
+pub enum LightKind {
+    /// Reaches every point equally from every direction
+    Ambient,
+    /// Infinitely far away. Holds the direction pointing towards the light.
+    Directional(Vec3),
+    /// Shines from a position in every direction
+    Point(Vec3),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    pub intensity: f32,
+    pub color: Color,
+}
+
+impl Light {
+    pub fn ambient(intensity: f32, color: Color) -> Self {
+        Self {
+            kind: LightKind::Ambient,
+            intensity,
+            color,
+        }
+    }
+
+    pub fn directional(direction: &Vec3, intensity: f32, color: Color) -> Self {
+        Self {
+            kind: LightKind::Directional(*direction),
+            intensity,
+            color,
+        }
+    }
+
+    pub fn point(position: &Vec3, intensity: f32, color: Color) -> Self {
+        Self {
+            kind: LightKind::Point(*position),
+            intensity,
+            color,
+        }
+    }
+
+    /// Moves the light by given transform. Directions ignore translation.
+    pub fn transform(&self, transform: &Mat4) -> Self {
+        let kind = match self.kind {
+            LightKind::Ambient => LightKind::Ambient,
+            LightKind::Directional(direction) => LightKind::Directional(
+                (transform * Vec4::new(direction.x, direction.y, direction.z, 0.0)).xyz(),
+            ),
+            LightKind::Point(position) => LightKind::Point(
+                (transform * Vec4::new(position.x, position.y, position.z, 1.0)).xyz(),
+            ),
+        };
+
+        Self { kind, ..*self }
+    }
+}
+
+/// Light reaching a point as RGB multipliers, split by how it is reflected
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lighting {
+    /// Ambient and diffuse light, tinted by the color of the surface
+    pub diffuse: Vec3,
+    /// Highlights, tinted by the specular color of the surface
+    pub specular: Vec3,
+}
+
+/// Sums the light reaching a point as an RGB multiplier.
+///
+/// Same as adding up both parts of [`compute_lighting_terms`].
+pub fn compute_lighting(
+    lights: &[Light],
+    point: &Vec3,
+    normal: &Vec3,
+    specular: Option<f32>,
+) -> Vec3 {
+    let lighting = compute_lighting_terms(lights, point, normal, specular);
+    lighting.diffuse + lighting.specular
+}
+
+/// Sums the light reaching a point, keeping highlights apart.
+///
+/// Everything is expected in camera space, where the viewer sits at the
+/// origin. Diffuse light follows Lambert's cosine law and specular light
+/// follows Phong's reflection model with given exponent, if any.
+///
+/// The normal doesn't need to be of unit length. If it has no length at all
+/// only ambient light reaches the point.
+pub fn compute_lighting_terms(
+    lights: &[Light],
+    point: &Vec3,
+    normal: &Vec3,
+    specular: Option<f32>,
+) -> Lighting {
+    let normal = normal.try_normalize(f32::EPSILON);
+    let view = -point;
+    let mut total = Lighting::default();
+    for light in lights {
+        let rgb = Vec3::from(light.color) * light.intensity;
+        let to_light = match light.kind {
+            LightKind::Ambient => {
+                total.diffuse += rgb;
+                continue;
+            }
+            LightKind::Directional(direction) => direction,
+            LightKind::Point(position) => position - point,
+        };
+        let Some(normal) = normal else {
+            continue;
+        };
+
+        // Diffuse
+        let n_dot_l = normal.dot(&to_light);
+        if n_dot_l > 0.0 {
+            total.diffuse += rgb * n_dot_l / to_light.norm();
+        }
+
+        // Specular
+        if let Some(exponent) = specular {
+            let reflected = 2.0 * normal * normal.dot(&to_light) - to_light;
+            let r_dot_v = reflected.dot(&view);
+            if r_dot_v > 0.0 {
+                total.specular += rgb * (r_dot_v / (reflected.norm() * view.norm())).powf(exponent);
+            }
+        }
+    }
+
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color;
+
+    fn lights() -> Vec<Light> {
+        vec![
+            Light::ambient(0.2, color::WHITE),
+            Light::point(&Vec3::new(2.0, 1.0, 0.0), 0.6, color::WHITE),
+            Light::directional(&Vec3::new(1.0, 4.0, 4.0), 0.2, color::WHITE),
+        ]
+    }
+
+    #[test]
+    fn normal_length_does_not_matter() {
+        let point = Vec3::new(0.5, -0.5, 8.0);
+        let normal = Vec3::new(0.3, 0.2, -1.0);
+        let unit = compute_lighting(&lights(), &point, &normal, Some(50.0));
+        for k in [1e-3, 0.5, 2.0, 1e3] {
+            let scaled = compute_lighting(&lights(), &point, &(normal * k), Some(50.0));
+            assert!((scaled - unit).norm() < 1e-4, "{k}: {scaled} != {unit}");
+        }
+    }
+
+    #[test]
+    fn zero_normal_gets_ambient_only() {
+        let light = compute_lighting(&lights(), &Vec3::new(0.0, 0.0, 8.0), &Vec3::zeros(), None);
+        assert!((light - Vec3::repeat(0.2)).norm() < 1e-6);
+    }
+}
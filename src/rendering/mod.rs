@@ -1,5 +1,12 @@
+pub mod backend;
 pub mod canvas;
+pub mod clipping;
+pub mod framebuffer;
+pub mod gltf;
+pub mod light;
 pub mod renderer;
 pub mod scene;
+pub mod scene_file;
+pub mod shader;
+pub mod texture;
 pub mod viewport;
-pub mod clipping;
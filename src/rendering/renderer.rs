@@ -1,61 +1,523 @@
+use crate::color;
 use crate::models::model::Instance;
 use crate::models::triangle::Triangle;
+use crate::models::vertex::Vertex;
 use crate::rendering::canvas::Canvas;
+use crate::rendering::clipping;
+use crate::rendering::light::Light;
 use crate::rendering::scene::Scene;
-use crate::rendering::viewport::Viewport;
-use nalgebra_glm::{Vec3, Vec4};
+use crate::rendering::shader::{
+    FlatShader, FragmentShader, GouraudShader, PhongShader, Primitive, ShadedVertex, Uniforms,
+    VertexShader,
+};
+use crate::rendering::viewport::{Plane, Viewport};
+use nalgebra_glm::{Mat4, Vec3, Vec4};
+use std::rc::Rc;
+use std::str::FromStr;
+
+/// Which triangles to throw away based on the way they face the camera
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CullMode {
+    /// Drop triangles facing away from the camera
+    Back,
+    /// Drop triangles facing towards the camera
+    Front,
+    /// Keep every triangle
+    None,
+}
+
+/// How triangles get drawn onto the canvas
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Triangle edges only
+    Wireframe,
+    /// Triangles filled with their flat color
+    Filled,
+    /// Triangles lit per vertex with the colors blended across them
+    Shaded,
+    /// Triangles lit per pixel from interpolated normals and positions
+    Phong,
+    /// Filled triangles with their edges drawn on top
+    FilledWireframe,
+}
+
+impl RenderMode {
+    /// Cycles through the modes in declaration order
+    pub fn next(self) -> Self {
+        match self {
+            RenderMode::Wireframe => RenderMode::Filled,
+            RenderMode::Filled => RenderMode::Shaded,
+            RenderMode::Shaded => RenderMode::Phong,
+            RenderMode::Phong => RenderMode::FilledWireframe,
+            RenderMode::FilledWireframe => RenderMode::Wireframe,
+        }
+    }
+}
+
+/// Parses the lowercase name of a mode, e.g. `phong` or `filled-wireframe`
+impl FromStr for RenderMode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "wireframe" => Ok(RenderMode::Wireframe),
+            "filled" => Ok(RenderMode::Filled),
+            "shaded" => Ok(RenderMode::Shaded),
+            "phong" => Ok(RenderMode::Phong),
+            "filled-wireframe" => Ok(RenderMode::FilledWireframe),
+            _ => Err(format!("unknown render mode {name}")),
+        }
+    }
+}
+
+/// How much closer (relative to 1/z) overlay edges are pulled so they win
+/// the depth test against the faces they outline
+const WIREFRAME_DEPTH_BIAS: f32 = 1e-3;
+
+/// Counters collected while rendering
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RenderStats {
+    pub culled_faces: usize,
+}
 
 pub struct Renderer {
     pub canvas: Canvas,
     pub viewport: Viewport,
+    pub cull_mode: CullMode,
+    pub render_mode: RenderMode,
+    /// Custom shaders used instead of those of the render mode
+    shaders: Option<(Rc<dyn VertexShader>, Rc<dyn FragmentShader>)>,
+    /// Lights of the scene being rendered, in camera space
+    lights: Vec<Light>,
+    stats: RenderStats,
 }
 
 impl Renderer {
     pub fn new(canvas: Canvas, viewport: Viewport) -> Self {
-        Self { canvas, viewport }
+        Self {
+            canvas,
+            viewport,
+            cull_mode: CullMode::Back,
+            render_mode: RenderMode::Wireframe,
+            shaders: None,
+            lights: vec![],
+            stats: RenderStats::default(),
+        }
     }
 
-    pub fn render_object(&mut self, vertices: &Vec<Vec4>, triangles: &Vec<Triangle>) {
-        let mut projected: Vec<Vec3> = Vec::new();
-        // Convert all 3d points into 2d points
-        vertices
-            .iter()
-            .for_each(|vertex| projected.push(self.viewport.project_vertex(vertex)));
-        // Render the triangles
-        triangles
-            .iter()
-            .for_each(|triangle| self.render_triangle(triangle, &projected));
+    /// Counters since the start of the last [`Renderer::render_scene`]
+    pub fn get_stats(&self) -> &RenderStats {
+        &self.stats
     }
 
-    pub fn render_triangle(&mut self, triangle: &Triangle, projected: &Vec<Vec3>) {
-        self.canvas.draw_wireframe_triangle(
-            &projected[triangle.vertices[0] as usize],
-            &projected[triangle.vertices[1] as usize],
-            &projected[triangle.vertices[2] as usize],
-            triangle.color,
-        );
+    /// Replaces the shaders of the render mode with custom ones. Wireframe
+    /// edges are still drawn in the color of each triangle.
+    ///
+    /// The vertex shader may put vertices anywhere, so instances are no
+    /// longer skipped based on their bounding sphere and every triangle is
+    /// clipped against the whole view volume.
+    pub fn set_shaders(&mut self, vertex: Rc<dyn VertexShader>, fragment: Rc<dyn FragmentShader>) {
+        self.shaders = Some((vertex, fragment));
+    }
+
+    /// Goes back to the shaders of the render mode
+    pub fn clear_shaders(&mut self) {
+        self.shaders = None;
+    }
+
+    /// The custom shaders if there are any, the ones of the render mode otherwise
+    fn shaders(&self) -> (Rc<dyn VertexShader>, Rc<dyn FragmentShader>) {
+        if let Some((vertex, fragment)) = &self.shaders {
+            return (Rc::clone(vertex), Rc::clone(fragment));
+        }
+        match self.render_mode {
+            RenderMode::Shaded => (Rc::new(GouraudShader), Rc::new(GouraudShader)),
+            RenderMode::Phong => (Rc::new(PhongShader), Rc::new(PhongShader)),
+            _ => (Rc::new(FlatShader), Rc::new(FlatShader)),
+        }
+    }
+
+    /// Draws triangles whose vertices are already in camera space, without
+    /// clipping them
+    pub fn render_object(&mut self, vertices: &[Vertex], triangles: &[Triangle]) {
+        self.render_triangles(vertices, triangles, Mat4::identity(), Mat4::identity(), &[]);
     }
 
     pub fn render_scene(&mut self, scene: &Scene) {
-        // TODO: Clip scene
+        self.stats = RenderStats::default();
+        // Without any lights, light the scene from the camera
+        self.lights = if scene.lights.is_empty() {
+            vec![Light::point(&Vec3::zeros(), 1.0, color::WHITE)]
+        } else {
+            let transform = self.viewport.get_transform();
+            scene
+                .lights
+                .iter()
+                .map(|light| light.borrow().transform(&transform))
+                .collect()
+        };
         for i in 0..scene.instances.len() {
             self.render_instance(&scene.instances[i].borrow());
         }
     }
 
     pub fn render_instance(&mut self, instance: &Instance) {
-        let mut projected: Vec<Vec3> = Vec::new();
-        // Convert all 3d points into 2d points
-        // TODO: Rebuild transform after converting to nalgebra glm
-        instance.get_model().vertices.iter().for_each(|vertex| {
-            projected.push(self.viewport.project_vertex(
-                &(self.viewport.get_transform() * instance.get_transform() * vertex),
-            ))
-        });
-        instance
-            .get_model()
-            .triangles
+        let model = instance.get_model();
+        // Move all 3d points into camera space
+        let transform = self.viewport.get_transform() * instance.get_transform();
+        // The camera transform is rigid, so it is its own inverse transpose
+        let normal_transform = self.viewport.get_transform() * instance.get_normal_transform();
+
+        // Skip the instance entirely if its bounding sphere is outside of any
+        // plane, and only clip against the planes the sphere straddles.
+        // Custom vertex shaders may move vertices out of the sphere.
+        let mut planes: Vec<Plane> = Vec::new();
+        if self.shaders.is_some() {
+            planes.extend(self.viewport.clip_space_planes());
+        } else {
+            let sphere = model.get_bounding_sphere().transform(&transform);
+            let camera_planes = self.viewport.clipping_planes();
+            for (plane, clip_plane) in camera_planes.iter().zip(self.viewport.clip_space_planes()) {
+                let distance = plane.signed_distance(&sphere.center);
+                if distance < -sphere.radius {
+                    return;
+                } else if distance < sphere.radius {
+                    planes.push(*clip_plane);
+                }
+            }
+        }
+
+        self.render_triangles(
+            &model.vertices,
+            &model.triangles,
+            transform,
+            normal_transform,
+            &planes,
+        );
+    }
+
+    /// Runs triangles through the shaders, clipping them against clip space
+    /// `planes` on the way.
+    ///
+    /// Every vertex goes through the vertex shader once for each specular
+    /// exponent of the triangles using it, except those without a normal,
+    /// which are shaded for every triangle with the normal of its face.
+    fn render_triangles(
+        &mut self,
+        vertices: &[Vertex],
+        triangles: &[Triangle],
+        transform: Mat4,
+        normal_transform: Mat4,
+        planes: &[Plane],
+    ) {
+        let lights = std::mem::take(&mut self.lights);
+        let mut uniforms = Uniforms {
+            transform,
+            normal_transform,
+            projection: self.viewport.get_projection(),
+            lights: &lights,
+            specular: None,
+        };
+        let (vertex_shader, fragment_shader) = self.shaders();
+        // Shaded vertices for each specular exponent, filled as they are used
+        let mut batches: Vec<(Option<u32>, Vec<Option<ShadedVertex>>)> = vec![];
+
+        for triangle in triangles {
+            let corners = triangle.vertices.map(|i| &vertices[i as usize]);
+            uniforms.specular = triangle.specular;
+            let key = triangle.specular.map(f32::to_bits);
+            let batch = match batches.iter().position(|(specular, _)| *specular == key) {
+                Some(batch) => batch,
+                None => {
+                    batches.push((key, vec![None; vertices.len()]));
+                    batches.len() - 1
+                }
+            };
+            let shaded = &mut batches[batch].1;
+
+            let mut faceted: [Option<ShadedVertex>; 3] = Default::default();
+            for (k, corner) in corners.iter().enumerate() {
+                if corner.normal.is_none() {
+                    let [p0, p1, p2] = corners.map(|corner| corner.position.xyz());
+                    let with_normal = Vertex {
+                        normal: (p1 - p0).cross(&(p2 - p0)).try_normalize(f32::EPSILON),
+                        ..(*corner).clone()
+                    };
+                    faceted[k] = Some(vertex_shader.shade(&uniforms, &with_normal));
+                } else if shaded[triangle.vertices[k] as usize].is_none() {
+                    shaded[triangle.vertices[k] as usize] =
+                        Some(vertex_shader.shade(&uniforms, corner));
+                }
+            }
+            let shaded: [&ShadedVertex; 3] = std::array::from_fn(|k| match &faceted[k] {
+                Some(vertex) => vertex,
+                None => shaded[triangle.vertices[k] as usize].as_ref().unwrap(),
+            });
+            let primitive = Primitive { triangle, corners };
+
+            // Throw away faces we would not see anyway
+            if self.is_culled(shaded.map(|vertex| &vertex.position)) {
+                self.stats.culled_faces += 1;
+                continue;
+            }
+
+            let inside = planes.iter().all(|plane| {
+                shaded
+                    .iter()
+                    .all(|vertex| plane.signed_distance(&vertex.position) >= 0.0)
+            });
+            if inside {
+                // Convert all 3d points into 2d points
+                let projected = shaded.map(|vertex| self.viewport.clip_to_canvas(&vertex.position));
+                self.draw_triangle(
+                    &uniforms,
+                    &primitive,
+                    [&projected[0], &projected[1], &projected[2]],
+                    shaded.map(|vertex| &vertex.varyings[..]),
+                    fragment_shader.as_ref(),
+                );
+                continue;
+            }
+
+            // Throw away everything outside of the view volume before dividing by w
+            let mut clipped: Vec<ShadedVertex> =
+                shaded.iter().map(|&vertex| vertex.clone()).collect();
+            let whole = Triangle {
+                vertices: [0, 1, 2],
+                ..triangle.clone()
+            };
+            let pieces = clipping::clip_triangles(&[whole], planes, &mut clipped);
+            let projected: Vec<Vec3> = clipped
+                .iter()
+                .map(|vertex| self.viewport.clip_to_canvas(&vertex.position))
+                .collect();
+            for piece in &pieces {
+                let [i0, i1, i2] = piece.vertices.map(|i| i as usize);
+                self.draw_triangle(
+                    &uniforms,
+                    &primitive,
+                    [&projected[i0], &projected[i1], &projected[i2]],
+                    [i0, i1, i2].map(|i| &clipped[i].varyings[..]),
+                    fragment_shader.as_ref(),
+                );
+            }
+        }
+
+        self.lights = lights;
+    }
+
+    /// Draws a triangle, or a clipped piece of one, according to
+    /// [`Renderer::render_mode`].
+    ///
+    /// `points` are its corners on the canvas and `varyings` what the vertex
+    /// shader handed on for them.
+    fn draw_triangle(
+        &mut self,
+        uniforms: &Uniforms,
+        primitive: &Primitive,
+        points: [&Vec3; 3],
+        varyings: [&[f32]; 3],
+        fragment_shader: &dyn FragmentShader,
+    ) {
+        let [p0, p1, p2] = points;
+        if self.render_mode != RenderMode::Wireframe {
+            self.canvas
+                .draw_interpolated_triangle(points, varyings, |fragment| {
+                    fragment_shader.shade(uniforms, primitive, fragment)
+                });
+        }
+        match self.render_mode {
+            RenderMode::Wireframe => {
+                self.canvas
+                    .draw_wireframe_triangle(p0, p1, p2, primitive.triangle.color);
+            }
+            RenderMode::FilledWireframe => {
+                let biased =
+                    [p0, p1, p2].map(|p| Vec3::new(p.x, p.y, p.z * (1.0 + WIREFRAME_DEPTH_BIAS)));
+                self.canvas.draw_wireframe_triangle(
+                    &biased[0],
+                    &biased[1],
+                    &biased[2],
+                    color::WHITE,
+                );
+            }
+            _ => {}
+        }
+    }
+
+    /// Checks a clip space triangle against [`Renderer::cull_mode`].
+    ///
+    /// Front faces wind so that `(v1 - v0) x (v2 - v0)` points out of the
+    /// model, as in [`default_cube`](crate::models::model::default_cube).
+    /// The determinant of the corners' x, y and w tells which way they wind
+    /// as seen from the camera, wherever they are relative to it. For the
+    /// projection of the viewport it has the sign of the camera space normal
+    /// dotted with the vector towards the triangle, which is positive when
+    /// the triangle faces away from us.
+    pub fn is_culled(&self, positions: [&Vec4; 3]) -> bool {
+        let [v0, v1, v2] = positions.map(|position| Vec3::new(position.x, position.y, position.w));
+        let facing = v0.dot(&v1.cross(&v2));
+
+        match self.cull_mode {
+            CullMode::Back => facing >= 0.0,
+            CullMode::Front => facing <= 0.0,
+            CullMode::None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::models::model::Model;
+    use crate::rendering::canvas::Fragment;
+    use crate::rendering::texture::{Texture, WrapMode};
+    use nalgebra_glm::Vec2;
+
+    const WIDTH: i32 = 160;
+    const HEIGHT: i32 = 90;
+
+    fn renderer() -> Renderer {
+        let canvas = Canvas::headless(WIDTH, HEIGHT);
+        let viewport = Viewport::new(12.80, 7.20, 6.0, &canvas);
+        let mut renderer = Renderer::new(canvas, viewport);
+        renderer.render_mode = RenderMode::Filled;
+        renderer.canvas.clear(color::BLACK);
+        renderer
+    }
+
+    /// Color of a pixel counted from the top left
+    fn pixel(renderer: &Renderer, x: i32, y: i32) -> Color {
+        renderer.canvas.framebuffer().get_pixel(x, y).unwrap()
+    }
+
+    #[test]
+    fn textures_are_perspective_correct() {
+        // A quad running away from the camera, from its bottom edge at z = 7
+        // to its top edge at z = 21, textured blue on top and white below
+        let corners = [
+            (Vec3::new(-2.0, -2.0, 7.0), Vec2::new(0.0, 1.0)),
+            (Vec3::new(2.0, -2.0, 7.0), Vec2::new(1.0, 1.0)),
+            (Vec3::new(2.0, 2.0, 21.0), Vec2::new(1.0, 0.0)),
+            (Vec3::new(-2.0, 2.0, 21.0), Vec2::new(0.0, 0.0)),
+        ];
+        let vertices: Vec<Vertex> = corners
             .iter()
-            .for_each(|triangle| self.render_triangle(triangle, &projected));
+            .map(|(position, uv)| Vertex {
+                uv: Some(*uv),
+                ..Vertex::new(&Vec4::new(position.x, position.y, position.z, 1.0))
+            })
+            .collect();
+        let texture =
+            Rc::new(Texture::new(1, 2, vec![color::BLUE, color::WHITE]).with_wrap(WrapMode::Clamp));
+        let triangles = [[0, 3, 2], [0, 2, 1]]
+            .map(|[a, b, c]| Triangle::new(a, b, c, color::RED).with_texture(Rc::clone(&texture)));
+
+        let mut renderer = renderer();
+        renderer.render_object(&vertices, &triangles);
+
+        // The texture changes halfway up the quad in 3D, at y = 0 and z = 14,
+        // which projects onto the middle row. Halfway between the projected
+        // edges would be 7 rows lower.
+        let column: Vec<Color> = (0..HEIGHT)
+            .map(|y| pixel(&renderer, WIDTH / 2, y))
+            .collect();
+        let first_white = column
+            .iter()
+            .position(|&color| color == color::WHITE)
+            .unwrap();
+        let last_blue = column
+            .iter()
+            .rposition(|&color| color == color::BLUE)
+            .unwrap();
+        assert_eq!(last_blue + 1, first_white);
+        assert!(
+            (HEIGHT / 2 - 1..=HEIGHT / 2 + 1).contains(&(first_white as i32)),
+            "white starts at row {first_white}"
+        );
+    }
+
+    /// Pushes geometry 20 units further away and hands on its column on the
+    /// canvas, counted from the middle
+    struct Push;
+
+    impl VertexShader for Push {
+        fn shade(&self, uniforms: &Uniforms, vertex: &Vertex) -> ShadedVertex {
+            let position = uniforms.transform_position(vertex) + Vec4::new(0.0, 0.0, 20.0, 0.0);
+            let clip = uniforms.project(&position);
+            ShadedVertex {
+                position: clip,
+                varyings: vec![clip.x / clip.w * WIDTH as f32 / 2.0],
+            }
+        }
+    }
+
+    /// Fills every other column with green and discards the rest
+    struct EveryOtherColumn;
+
+    impl FragmentShader for EveryOtherColumn {
+        fn shade(&self, _: &Uniforms, _: &Primitive, fragment: &Fragment) -> Option<Color> {
+            let column = WIDTH / 2 + fragment.values[0].round() as i32;
+            column.rem_euclid(2).eq(&0).then_some(color::GREEN)
+        }
+    }
+
+    #[test]
+    fn custom_shaders() {
+        // Behind the camera until the vertex shader pushes it to z = 10,
+        // where it runs off the left edge of the canvas and ends 15 columns
+        // right of the middle
+        let positions = vec![
+            Vec4::new(-14.0, 1.0, -10.0, 1.0),
+            Vec4::new(2.0, 1.0, -10.0, 1.0),
+            Vec4::new(2.0, -1.0, -10.0, 1.0),
+            Vec4::new(-14.0, -1.0, -10.0, 1.0),
+        ];
+        let triangles = vec![
+            Triangle::new(0, 1, 2, color::RED),
+            Triangle::new(0, 2, 3, color::RED),
+        ];
+        let mut scene = Scene::new();
+        scene.add_instance(Rc::new(std::cell::RefCell::new(Instance::new(
+            Rc::new(Model::new(positions, triangles)),
+            &Vec4::new(1.0, 1.0, 1.0, 0.0),
+            &Vec4::zeros(),
+            &Vec4::zeros(),
+        ))));
+
+        let mut renderer = renderer();
+        // Without custom shaders the instance is skipped as being behind us
+        renderer.render_scene(&scene);
+        assert!(renderer
+            .canvas
+            .framebuffer()
+            .pixels()
+            .iter()
+            .all(|&pixel| pixel == 0));
+
+        renderer.set_shaders(Rc::new(Push), Rc::new(EveryOtherColumn));
+        renderer.render_scene(&scene);
+        let depth = |x: i32, y: i32| renderer.canvas.framebuffer().get_depth(x, y).unwrap();
+        let y = HEIGHT / 2;
+        // The rasterizer interpolates from the clipped edge, which can shift
+        // which parity is drawn, so only check that columns alternate
+        let first = pixel(&renderer, 0, y) == color::GREEN;
+        for x in 0..=WIDTH / 2 + 15 {
+            if (x % 2 == 0) == first {
+                assert_eq!(pixel(&renderer, x, y), color::GREEN, "column {x}");
+                assert!((depth(x, y) - 0.1).abs() < 1e-6, "column {x}");
+            } else {
+                // Discarded fragments leave the depth buffer alone
+                assert_eq!(pixel(&renderer, x, y), color::BLACK, "column {x}");
+                assert_eq!(depth(x, y), 0.0, "column {x}");
+            }
+        }
+        for x in WIDTH / 2 + 16..WIDTH {
+            assert_eq!(pixel(&renderer, x, y), color::BLACK, "column {x}");
+        }
+        for x in 0..WIDTH {
+            assert_eq!(pixel(&renderer, x, 0), color::BLACK, "column {x}");
+        }
     }
 }
@@ -1,17 +1,37 @@
 use crate::models::model::Instance;
+use crate::rendering::light::Light;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+#[derive(Default)]
 pub struct Scene {
     pub instances: Vec<Rc<RefCell<Instance>>>,
+    pub lights: Vec<Rc<RefCell<Light>>>,
 }
 
 impl Scene {
     pub fn new() -> Self {
-        Self { instances: vec![] }
+        Self {
+            instances: vec![],
+            lights: vec![],
+        }
     }
 
     pub fn add_instance(&mut self, instance: Rc<RefCell<Instance>>) {
         self.instances.push(instance);
     }
+
+    /// Removes given instance if it is part of the scene
+    pub fn remove_instance(&mut self, instance: &Rc<RefCell<Instance>>) {
+        self.instances.retain(|other| !Rc::ptr_eq(other, instance));
+    }
+
+    pub fn add_light(&mut self, light: Rc<RefCell<Light>>) {
+        self.lights.push(light);
+    }
+
+    /// Removes given light if it is part of the scene
+    pub fn remove_light(&mut self, light: &Rc<RefCell<Light>>) {
+        self.lights.retain(|other| !Rc::ptr_eq(other, light));
+    }
 }
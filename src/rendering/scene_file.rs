@@ -0,0 +1,197 @@
+//! A plain text description of a [`Scene`].
+//!
+//! Every line holds one record. Blank lines and anything after `#` are ignored.
+//!
+//! ```text
+//! # A cube ten units in front of the camera, turned a little
+//! instance cube translate 0 0 10 rotate 0 0.5 0 scale 1 1 1
+//! instance models/teapot.obj translate 3 0 12
+//! ambient 0.2
+//! point 2 1 0 0.6
+//! directional 1 4 4 0.2 255 255 255
+//! ```
+//!
+//! Instances name either a built in shape (`cube`, `sphere`, `icosphere`,
+//! `cylinder`, `cone`, `torus`, `plane` or `capsule`, each about two units
+//! across) or a model file, relative to the scene file. `translate`,
+//! `rotate` (radians) and `scale` are optional. Lights take an optional
+//! color as three channels between 0 and 255 and default to white.
+
+use crate::color;
+use crate::color::Color;
+use crate::models::model::{default_cube, Instance, Model};
+use crate::models::obj;
+use crate::models::ply;
+use crate::models::shapes;
+use crate::models::stl;
+use crate::rendering::light::Light;
+use crate::rendering::scene::Scene;
+use crate::text::numbers;
+use nalgebra_glm::{Vec3, Vec4};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+
+#[derive(Debug)]
+pub enum SceneFileError {
+    Io(std::io::Error),
+    /// A record we could not make sense of, on a 1-based line number
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneFileError::Io(error) => write!(f, "{error}"),
+            SceneFileError::Parse { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneFileError {}
+
+impl From<std::io::Error> for SceneFileError {
+    fn from(error: std::io::Error) -> Self {
+        SceneFileError::Io(error)
+    }
+}
+
+/// Reads and parses a scene file
+pub fn load(path: impl AsRef<Path>) -> Result<Scene, SceneFileError> {
+    let path = path.as_ref();
+    parse(
+        &std::fs::read_to_string(path)?,
+        path.parent().unwrap_or(Path::new("")),
+    )
+}
+
+/// Parses a scene description, loading model files relative to `base`
+pub fn parse(text: &str, base: &Path) -> Result<Scene, SceneFileError> {
+    let mut scene = Scene::new();
+    // Every instance of the same model shares it
+    let mut models: HashMap<&str, Rc<Model>> = HashMap::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: String| SceneFileError::Parse {
+            line: i + 1,
+            message,
+        };
+        let line = line.split('#').next().unwrap();
+        let mut words = line.split_whitespace();
+        let Some(record) = words.next() else {
+            continue;
+        };
+        let words: Vec<&str> = words.collect();
+
+        match record {
+            "instance" => {
+                let (&name, mut rest) = words
+                    .split_first()
+                    .ok_or_else(|| error("instance needs a model".to_string()))?;
+                let model = match models.get(name) {
+                    Some(model) => Rc::clone(model),
+                    None => {
+                        let model = Rc::new(load_model(name, base).map_err(error)?);
+                        models.insert(name, Rc::clone(&model));
+                        model
+                    }
+                };
+
+                let mut translation = Vec4::new(0.0, 0.0, 0.0, 0.0);
+                let mut rotation = Vec4::new(0.0, 0.0, 0.0, 0.0);
+                let mut scale = Vec4::new(1.0, 1.0, 1.0, 0.0);
+                while let Some((&keyword, values)) = rest.split_first() {
+                    let target = match keyword {
+                        "translate" => &mut translation,
+                        "rotate" => &mut rotation,
+                        "scale" => &mut scale,
+                        _ => return Err(error(format!("unexpected {keyword}"))),
+                    };
+                    let vector = numbers::<3>(values).map_err(error)?;
+                    *target = Vec4::new(vector[0], vector[1], vector[2], 0.0);
+                    rest = &values[3..];
+                }
+
+                scene.add_instance(Rc::new(RefCell::new(Instance::new(
+                    model,
+                    &scale,
+                    &rotation,
+                    &translation,
+                ))));
+            }
+            "ambient" => {
+                let [intensity] = numbers::<1>(&words).map_err(error)?;
+                let color = light_color(&words[1..]).map_err(error)?;
+                scene.add_light(Rc::new(RefCell::new(Light::ambient(intensity, color))));
+            }
+            "point" | "directional" => {
+                let [x, y, z, intensity] = numbers::<4>(&words).map_err(error)?;
+                let color = light_color(&words[4..]).map_err(error)?;
+                let vector = Vec3::new(x, y, z);
+                let light = match record {
+                    "point" => Light::point(&vector, intensity, color),
+                    _ => Light::directional(&vector, intensity, color),
+                };
+                scene.add_light(Rc::new(RefCell::new(light)));
+            }
+            _ => return Err(error(format!("unknown record {record}"))),
+        }
+    }
+
+    Ok(scene)
+}
+
+/// Builds the built in model called `name` or loads it from a file
+fn load_model(name: &str, base: &Path) -> Result<Model, String> {
+    let shape = match name {
+        "cube" => Some(default_cube()),
+        "sphere" => Some(shapes::uv_sphere(1.0, 32, 16)),
+        "icosphere" => Some(shapes::icosphere(1.0, 3)),
+        "cylinder" => Some(shapes::cylinder(1.0, 2.0, 32)),
+        "cone" => Some(shapes::cone(1.0, 2.0, 32)),
+        "torus" => Some(shapes::torus(1.0, 0.3, 32, 16)),
+        "plane" => Some(shapes::plane(2.0, 2.0, 1, 1)),
+        "capsule" => Some(shapes::capsule(0.5, 1.0, 32, 8)),
+        _ => None,
+    };
+    if let Some(shape) = shape {
+        return Ok(shape);
+    }
+
+    let path = base.join(name);
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    match extension
+        .map(|extension| extension.to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("obj") => obj::load(&path)
+            .map(|obj| obj.model)
+            .map_err(|error| format!("{name}: {error}")),
+        Some("ply") => ply::load(&path).map_err(|error| format!("{name}: {error}")),
+        Some("stl") => stl::load(&path).map_err(|error| format!("{name}: {error}")),
+        _ => Err(format!("unknown model {name}")),
+    }
+}
+
+/// Parses what is left of a light record as an optional color
+fn light_color(words: &[&str]) -> Result<Color, String> {
+    match words {
+        [] => Ok(color::WHITE),
+        [r, g, b] => {
+            let channel = |word: &str| {
+                word.parse::<u8>()
+                    .map_err(|_| format!("expected a channel between 0 and 255, found {word}"))
+            };
+            Ok(Color::new(channel(r)?, channel(g)?, channel(b)?))
+        }
+        _ => Err(format!(
+            "expected a color as 3 channels, found {}",
+            words.join(" ")
+        )),
+    }
+}
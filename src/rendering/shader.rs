@@ -0,0 +1,324 @@
+//! Programmable stages of the pipeline.
+//!
+//! A [`VertexShader`] runs once for every vertex of an instance. It places
+//! the vertex in clip space and works out the values to blend across the
+//! triangles using it, its varyings. The renderer clips the triangles there,
+//! divides by w and maps them onto the canvas, which interpolates the
+//! varyings perspective-correctly. A [`FragmentShader`] then turns them into
+//! the color of each pixel, knowing the triangle being drawn. The built-in
+//! render modes are made of the shaders at the bottom of this module.
+
+use crate::color;
+use crate::color::Color;
+use crate::models::triangle::Triangle;
+use crate::models::vertex::Vertex;
+use crate::rendering::canvas::Fragment;
+use crate::rendering::clipping::ClipVertex;
+use crate::rendering::light;
+use crate::rendering::light::{Light, Lighting};
+use crate::rendering::texture::Texture;
+use nalgebra_glm::{Mat4, Vec2, Vec3, Vec4};
+
+/// What stays the same for every vertex and pixel of a batch of triangles
+pub struct Uniforms<'a> {
+    /// From model to camera space
+    pub transform: Mat4,
+    /// From model to camera space for normals
+    pub normal_transform: Mat4,
+    /// From camera to clip space, see [`Viewport::get_projection`](crate::rendering::viewport::Viewport::get_projection)
+    pub projection: Mat4,
+    /// Lights of the scene in camera space
+    pub lights: &'a [Light],
+    /// Specular exponent of the triangles being drawn. Vertices are shaded
+    /// again for every exponent used by a model.
+    pub specular: Option<f32>,
+}
+
+impl Uniforms<'_> {
+    /// Moves a model space vertex into camera space
+    pub fn transform_position(&self, vertex: &Vertex) -> Vec4 {
+        self.transform * vertex.position
+    }
+
+    /// Moves a model space normal into camera space
+    pub fn transform_normal(&self, normal: &Vec3) -> Vec3 {
+        (self.normal_transform * Vec4::new(normal.x, normal.y, normal.z, 0.0)).xyz()
+    }
+
+    /// Moves a camera space position into clip space
+    pub fn project(&self, position: &Vec4) -> Vec4 {
+        self.projection * position
+    }
+}
+
+/// The triangle being drawn along with its corners in model space
+pub struct Primitive<'a> {
+    pub triangle: &'a Triangle,
+    pub corners: [&'a Vertex; 3],
+}
+
+impl Primitive<'_> {
+    /// The texture of the triangle, if every corner knows where it sits on it
+    pub fn texture(&self) -> Option<&Texture> {
+        self.triangle
+            .texture
+            .as_deref()
+            .filter(|_| self.corners.iter().all(|corner| corner.uv.is_some()))
+    }
+
+    /// Whether every corner has a color overriding that of the triangle
+    pub fn has_colors(&self) -> bool {
+        self.corners.iter().all(|corner| corner.color.is_some())
+    }
+
+    /// Color of highlights on the triangle, `base` unless it has its own
+    pub fn specular_color(&self, base: Vec3) -> Vec3 {
+        self.triangle.specular_color.map_or(base, Vec3::from)
+    }
+
+    /// Combines the light reaching a pixel with the color of the surface there
+    pub fn reflect(&self, base: Vec3, lighting: &Lighting) -> Color {
+        Color::from(
+            base.component_mul(&lighting.diffuse)
+                + self.specular_color(base).component_mul(&lighting.specular),
+        )
+    }
+
+    /// Normal following the winding in camera space. Its length is twice
+    /// the area of the triangle.
+    pub fn face_normal(&self, uniforms: &Uniforms) -> Vec3 {
+        let [v0, v1, v2] = self
+            .corners
+            .map(|corner| uniforms.transform_position(corner).xyz());
+        (v1 - v0).cross(&(v2 - v0))
+    }
+}
+
+/// A vertex on its way to the canvas
+#[derive(Clone, Debug)]
+pub struct ShadedVertex {
+    /// Clip space position. Triangles are clipped there before being divided
+    /// by w and mapped onto the canvas.
+    pub position: Vec4,
+    /// Values interpolated across the triangle for the fragment shader
+    pub varyings: Vec<f32>,
+}
+
+impl ClipVertex for ShadedVertex {
+    fn position(&self) -> &Vec4 {
+        &self.position
+    }
+
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Self {
+            position: self.position.lerp(&other.position, t),
+            varyings: self
+                .varyings
+                .iter()
+                .zip(&other.varyings)
+                .map(|(a, b)| a + t * (b - a))
+                .collect(),
+        }
+    }
+}
+
+pub trait VertexShader {
+    /// Shades a model space vertex.
+    ///
+    /// Every vertex of a model must hand on the same number of varyings.
+    /// Vertices without a normal are given the normal of the face and shaded
+    /// again for every triangle using them.
+    fn shade(&self, uniforms: &Uniforms, vertex: &Vertex) -> ShadedVertex;
+}
+
+pub trait FragmentShader {
+    /// Picks the color of a pixel covered by the triangle from the varyings
+    /// in `fragment`, or discards the pixel by returning `None`. Discarded
+    /// pixels leave the depth buffer untouched.
+    fn shade(
+        &self,
+        uniforms: &Uniforms,
+        primitive: &Primitive,
+        fragment: &Fragment,
+    ) -> Option<Color>;
+}
+
+/// Fills triangles with their color, or their texture if they have one.
+///
+/// Varyings are the texture coordinates, zero for vertices without any.
+pub struct FlatShader;
+
+impl VertexShader for FlatShader {
+    fn shade(&self, uniforms: &Uniforms, vertex: &Vertex) -> ShadedVertex {
+        let uv = vertex.uv.unwrap_or_else(Vec2::zeros);
+        ShadedVertex {
+            position: uniforms.project(&uniforms.transform_position(vertex)),
+            varyings: vec![uv.x, uv.y],
+        }
+    }
+}
+
+impl FragmentShader for FlatShader {
+    fn shade(&self, _: &Uniforms, primitive: &Primitive, fragment: &Fragment) -> Option<Color> {
+        Some(match primitive.texture() {
+            Some(texture) => fragment.sample(texture, 0),
+            None => primitive.triangle.color,
+        })
+    }
+}
+
+/// Lights every vertex and blends the result across the triangle (Gouraud
+/// shading).
+///
+/// Varyings are the diffuse and specular light, the color of the vertex lit
+/// by each of them and the texture coordinates. Vertices without a color or
+/// texture coordinates hand on white and zero instead.
+pub struct GouraudShader;
+
+impl VertexShader for GouraudShader {
+    fn shade(&self, uniforms: &Uniforms, vertex: &Vertex) -> ShadedVertex {
+        let position = uniforms.transform_position(vertex);
+        let normal = match &vertex.normal {
+            Some(normal) => uniforms.transform_normal(normal),
+            None => Vec3::zeros(),
+        };
+        let lighting = light::compute_lighting_terms(
+            uniforms.lights,
+            &position.xyz(),
+            &normal,
+            uniforms.specular,
+        );
+        let diffuse = lighting.diffuse * vertex.intensity;
+        let specular = lighting.specular * vertex.intensity;
+        let color = Vec3::from(vertex.color.unwrap_or(color::WHITE));
+        let (color_diffuse, color_specular) = (
+            color.component_mul(&diffuse),
+            color.component_mul(&specular),
+        );
+        let uv = vertex.uv.unwrap_or_else(Vec2::zeros);
+
+        let mut varyings = Vec::with_capacity(14);
+        for value in [diffuse, specular, color_diffuse, color_specular] {
+            varyings.extend(value.iter());
+        }
+        varyings.extend([uv.x, uv.y]);
+        ShadedVertex {
+            position: uniforms.project(&position),
+            varyings,
+        }
+    }
+}
+
+impl FragmentShader for GouraudShader {
+    fn shade(&self, _: &Uniforms, primitive: &Primitive, fragment: &Fragment) -> Option<Color> {
+        let a = fragment.values;
+        let lighting = Lighting {
+            diffuse: Vec3::new(a[0], a[1], a[2]),
+            specular: Vec3::new(a[3], a[4], a[5]),
+        };
+        Some(match primitive.texture() {
+            Some(texture) => primitive.reflect(Vec3::from(fragment.sample(texture, 12)), &lighting),
+            // Vertex colors vary across the triangle, so they were lit per vertex
+            None if primitive.has_colors() => {
+                let diffuse = Vec3::new(a[6], a[7], a[8]);
+                let specular = match primitive.triangle.specular_color {
+                    Some(color) => Vec3::from(color).component_mul(&lighting.specular),
+                    None => Vec3::new(a[9], a[10], a[11]),
+                };
+                Color::from(diffuse + specular)
+            }
+            None => primitive.reflect(Vec3::from(primitive.triangle.color), &lighting),
+        })
+    }
+}
+
+/// Lights every pixel from the interpolated position and normal (Phong
+/// shading).
+///
+/// Varyings are the camera space position, normal, color, intensity and
+/// texture coordinates, followed by the extra attributes of the vertex.
+pub struct PhongShader;
+
+impl VertexShader for PhongShader {
+    fn shade(&self, uniforms: &Uniforms, vertex: &Vertex) -> ShadedVertex {
+        let position = uniforms.transform_position(vertex);
+        let normal = match &vertex.normal {
+            Some(normal) => uniforms.transform_normal(normal),
+            None => Vec3::zeros(),
+        };
+        let color = Vec3::from(vertex.color.unwrap_or(color::WHITE));
+        let uv = vertex.uv.unwrap_or_else(Vec2::zeros);
+
+        let mut varyings = vec![
+            position.x,
+            position.y,
+            position.z,
+            normal.x,
+            normal.y,
+            normal.z,
+            color.x,
+            color.y,
+            color.z,
+            vertex.intensity,
+            uv.x,
+            uv.y,
+        ];
+        varyings.extend(&vertex.extra);
+        ShadedVertex {
+            position: uniforms.project(&position),
+            varyings,
+        }
+    }
+}
+
+impl FragmentShader for PhongShader {
+    fn shade(
+        &self,
+        uniforms: &Uniforms,
+        primitive: &Primitive,
+        fragment: &Fragment,
+    ) -> Option<Color> {
+        let a = fragment.values;
+        let position = Vec3::new(a[0], a[1], a[2]);
+        let normal = Vec3::new(a[3], a[4], a[5]);
+        let mut lighting =
+            light::compute_lighting_terms(uniforms.lights, &position, &normal, uniforms.specular);
+        lighting.diffuse *= a[9];
+        lighting.specular *= a[9];
+        let base = match primitive.texture() {
+            Some(texture) => Vec3::from(fragment.sample(texture, 10)),
+            None if primitive.has_colors() => Vec3::new(a[6], a[7], a[8]),
+            None => Vec3::from(primitive.triangle.color),
+        };
+        Some(primitive.reflect(base, &lighting))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights_take_the_specular_color() {
+        let vertex = Vertex::new(&Vec4::new(0.0, 0.0, 0.0, 1.0));
+        let lighting = Lighting {
+            diffuse: Vec3::repeat(0.5),
+            specular: Vec3::repeat(0.5),
+        };
+        let base = Vec3::new(1.0, 0.0, 0.0);
+
+        let mut triangle = Triangle::new(0, 0, 0, color::RED);
+        let primitive = Primitive {
+            triangle: &triangle,
+            corners: [&vertex; 3],
+        };
+        assert_eq!(primitive.reflect(base, &lighting), color::RED);
+
+        triangle.specular_color = Some(color::BLUE);
+        let primitive = Primitive {
+            triangle: &triangle,
+            corners: [&vertex; 3],
+        };
+        assert_eq!(primitive.reflect(base, &lighting), Color::new(128, 0, 128));
+    }
+}
@@ -0,0 +1,370 @@
+use crate::color::Color;
+use nalgebra_glm::{Vec2, Vec3};
+
+/// What happens to texture coordinates outside of 0 to 1
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum WrapMode {
+    /// Tile the texture
+    Repeat,
+    /// Stretch the edge pixels outwards
+    Clamp,
+    /// Tile the texture, flipping every other copy
+    Mirror,
+}
+
+/// How texture pixels get combined into a sample
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FilterMode {
+    /// Nearest pixel of the nearest mip level
+    Nearest,
+    /// Blend of the four nearest pixels of the nearest mip level
+    Bilinear,
+    /// Blend of bilinear samples from the two nearest mip levels
+    Trilinear,
+}
+
+/// One resolution of a texture
+struct MipLevel {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl MipLevel {
+    fn get_pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Halves the resolution, averaging each 2x2 block of pixels
+    fn downsample(&self) -> Self {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = Vec3::zeros();
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let sx = (2 * x + dx).min(self.width - 1);
+                    let sy = (2 * y + dy).min(self.height - 1);
+                    sum += Vec3::from(self.get_pixel(sx, sy));
+                }
+                pixels.push(Color::from(sum / 4.0));
+            }
+        }
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+/// An image that can be sampled with texture coordinates.
+///
+/// (0, 0) is the top left corner of the image and (1, 1) the bottom right.
+pub struct Texture {
+    /// Full resolution first, each following level half the size of the previous
+    levels: Vec<MipLevel>,
+    pub wrap: WrapMode,
+    pub filter: FilterMode,
+}
+
+impl Texture {
+    /// Creates a texture from row-major pixels, top left first
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert!(width > 0 && height > 0, "texture must not be empty");
+        assert_eq!(pixels.len(), width * height, "one color per pixel");
+        Self {
+            levels: vec![MipLevel {
+                width,
+                height,
+                pixels,
+            }],
+            wrap: WrapMode::Repeat,
+            filter: FilterMode::Nearest,
+        }
+    }
+
+    pub fn with_wrap(mut self, wrap: WrapMode) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: FilterMode) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Builds the mip chain by repeatedly halving the texture down to a single pixel
+    pub fn with_mipmaps(mut self) -> Self {
+        self.levels.truncate(1);
+        loop {
+            let last = self.levels.last().unwrap();
+            if last.width == 1 && last.height == 1 {
+                break;
+            }
+            let next = last.downsample();
+            self.levels.push(next);
+        }
+        self
+    }
+
+    /// Creates a texture of `cells` by `cells` squares alternating between two colors
+    pub fn checkerboard(size: usize, cells: usize, a: Color, b: Color) -> Self {
+        let cell = (size / cells.max(1)).max(1);
+        let pixels = (0..size * size)
+            .map(|i| {
+                let (x, y) = (i % size, i / size);
+                if (x / cell + y / cell).is_multiple_of(2) {
+                    a
+                } else {
+                    b
+                }
+            })
+            .collect();
+
+        Self::new(size, size, pixels)
+    }
+
+    pub fn width(&self) -> usize {
+        self.levels[0].width
+    }
+
+    pub fn height(&self) -> usize {
+        self.levels[0].height
+    }
+
+    /// Number of mip levels, including the full resolution one
+    pub fn mip_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> Color {
+        self.levels[0].get_pixel(x, y)
+    }
+
+    /// Samples the full resolution texture
+    pub fn sample(&self, uv: &Vec2) -> Color {
+        self.sample_level(uv, 0.0)
+    }
+
+    /// Samples the texture given how far the texture coordinates move per
+    /// pixel to the right (`duv_dx`) and per pixel up (`duv_dy`).
+    ///
+    /// The further they move, the smaller the mip level that gets used.
+    pub fn sample_grad(&self, uv: &Vec2, duv_dx: &Vec2, duv_dy: &Vec2) -> Color {
+        let size = Vec2::new(self.width() as f32, self.height() as f32);
+        let footprint = duv_dx
+            .component_mul(&size)
+            .norm()
+            .max(duv_dy.component_mul(&size).norm());
+        let lod = if footprint > 1.0 {
+            footprint.log2()
+        } else {
+            0.0
+        };
+        self.sample_level(uv, lod)
+    }
+
+    /// Samples the texture at a fractional mip level
+    pub fn sample_level(&self, uv: &Vec2, lod: f32) -> Color {
+        let lod = lod.clamp(0.0, (self.levels.len() - 1) as f32);
+        match self.filter {
+            FilterMode::Nearest => self.nearest(&self.levels[lod.round() as usize], uv),
+            FilterMode::Bilinear => self.bilinear(&self.levels[lod.round() as usize], uv),
+            FilterMode::Trilinear => {
+                let lower = lod.floor() as usize;
+                let upper = (lower + 1).min(self.levels.len() - 1);
+                let a = self.bilinear(&self.levels[lower], uv);
+                let b = self.bilinear(&self.levels[upper], uv);
+                a.lerp(b, lod.fract())
+            }
+        }
+    }
+
+    fn nearest(&self, level: &MipLevel, uv: &Vec2) -> Color {
+        let x = wrap(
+            (uv.x * level.width as f32).floor() as i64,
+            level.width,
+            self.wrap,
+        );
+        let y = wrap(
+            (uv.y * level.height as f32).floor() as i64,
+            level.height,
+            self.wrap,
+        );
+        level.get_pixel(x, y)
+    }
+
+    fn bilinear(&self, level: &MipLevel, uv: &Vec2) -> Color {
+        // Pixel centers sit at half coordinates
+        let x = uv.x * level.width as f32 - 0.5;
+        let y = uv.y * level.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        let pixel = |dx: i64, dy: i64| {
+            level.get_pixel(
+                wrap(x0 as i64 + dx, level.width, self.wrap),
+                wrap(y0 as i64 + dy, level.height, self.wrap),
+            )
+        };
+        let top = pixel(0, 0).lerp(pixel(1, 0), fx);
+        let bottom = pixel(0, 1).lerp(pixel(1, 1), fx);
+        top.lerp(bottom, fy)
+    }
+}
+
+/// Maps a pixel coordinate onto `0..size` following the wrap mode
+fn wrap(i: i64, size: usize, mode: WrapMode) -> usize {
+    let size = size as i64;
+    let wrapped = match mode {
+        WrapMode::Repeat => i.rem_euclid(size),
+        WrapMode::Clamp => i.clamp(0, size - 1),
+        WrapMode::Mirror => {
+            let i = i.rem_euclid(2 * size);
+            if i < size {
+                i
+            } else {
+                2 * size - 1 - i
+            }
+        }
+    };
+
+    wrapped as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color;
+
+    fn sizes(texture: &Texture) -> Vec<(usize, usize)> {
+        texture
+            .levels
+            .iter()
+            .map(|level| (level.width, level.height))
+            .collect()
+    }
+
+    /// A 16x16 texture whose mip level k is filled with gray `k * 50`,
+    /// so samples show which levels were used
+    fn gray_levels() -> Texture {
+        let mut texture = Texture::new(16, 16, vec![color::BLACK; 256]).with_mipmaps();
+        for (k, level) in texture.levels.iter_mut().enumerate() {
+            let gray = (k * 50) as u8;
+            level.pixels.fill(Color::new(gray, gray, gray));
+        }
+        texture
+    }
+
+    #[test]
+    fn mip_chain_goes_down_to_one_pixel() {
+        let texture = |width, height| {
+            Texture::new(width, height, vec![color::WHITE; width * height]).with_mipmaps()
+        };
+        assert_eq!(sizes(&texture(8, 8)), [(8, 8), (4, 4), (2, 2), (1, 1)]);
+        assert_eq!(sizes(&texture(5, 3)), [(5, 3), (2, 1), (1, 1)]);
+        assert_eq!(sizes(&texture(1, 6)), [(1, 6), (1, 3), (1, 1)]);
+        assert_eq!(sizes(&texture(1, 1)), [(1, 1)]);
+        // Building the chain again starts over from the full resolution
+        assert_eq!(texture(4, 4).with_mipmaps().mip_levels(), 3);
+    }
+
+    #[test]
+    fn downsampling_averages_blocks() {
+        let pixels = vec![color::BLACK, color::WHITE, color::WHITE, color::BLACK];
+        let texture = Texture::new(2, 2, pixels).with_mipmaps();
+        assert_eq!(texture.levels[1].pixels, [Color::new(128, 128, 128)]);
+
+        // Odd sizes repeat the last row and column
+        let texture =
+            Texture::new(3, 1, vec![color::BLACK, color::BLACK, color::WHITE]).with_mipmaps();
+        assert_eq!(texture.levels[1].pixels, [color::BLACK]);
+    }
+
+    #[test]
+    fn footprint_picks_the_mip_level() {
+        let texture = gray_levels();
+        let uv = Vec2::new(0.5, 0.5);
+        let level = |duv_dx: Vec2, duv_dy: Vec2| texture.sample_grad(&uv, &duv_dx, &duv_dy).r;
+
+        // One texel per pixel or less stays at full resolution
+        assert_eq!(level(Vec2::new(1.0 / 16.0, 0.0), Vec2::zeros()), 0);
+        assert_eq!(level(Vec2::new(0.01, 0.0), Vec2::new(0.0, 0.01)), 0);
+        // Four texels per pixel is level 2, whichever direction is longer
+        assert_eq!(level(Vec2::new(4.0 / 16.0, 0.0), Vec2::zeros()), 100);
+        assert_eq!(level(Vec2::zeros(), Vec2::new(0.0, 4.0 / 16.0)), 100);
+        assert_eq!(
+            level(Vec2::new(0.0, 1.0 / 16.0), Vec2::new(4.0 / 16.0, 0.0)),
+            100
+        );
+        // Beyond the smallest level it stays there
+        assert_eq!(level(Vec2::new(100.0, 0.0), Vec2::zeros()), 200);
+    }
+
+    #[test]
+    fn filters_blend_levels() {
+        let uv = Vec2::new(0.5, 0.5);
+        let texture = gray_levels();
+        assert_eq!(texture.sample_level(&uv, 1.4).r, 50);
+        assert_eq!(texture.sample_level(&uv, 1.6).r, 100);
+
+        let texture = gray_levels().with_filter(FilterMode::Trilinear);
+        assert_eq!(texture.sample_level(&uv, 1.5).r, 75);
+        assert_eq!(texture.sample_level(&uv, 1.25).r, 63);
+        // 2^1.5 texels per pixel lands halfway between levels 1 and 2
+        let duv_dx = Vec2::new(2.0f32.powf(1.5) / 16.0, 0.0);
+        assert_eq!(texture.sample_grad(&uv, &duv_dx, &Vec2::zeros()).r, 75);
+    }
+
+    #[test]
+    fn wrap_modes() {
+        use WrapMode::*;
+        // Coordinate, then where Repeat, Clamp and Mirror put it on 4 pixels
+        let table = [
+            (-9, [3, 0, 0]),
+            (-5, [3, 0, 3]),
+            (-4, [0, 0, 3]),
+            (-1, [3, 0, 0]),
+            (0, [0, 0, 0]),
+            (2, [2, 2, 2]),
+            (3, [3, 3, 3]),
+            (4, [0, 3, 3]),
+            (5, [1, 3, 2]),
+            (7, [3, 3, 0]),
+            (8, [0, 3, 0]),
+            (13, [1, 3, 2]),
+        ];
+        for (i, expected) in table {
+            let found = [Repeat, Clamp, Mirror].map(|mode| wrap(i, 4, mode));
+            assert_eq!(found, expected, "{i}");
+        }
+        // A single pixel covers everything
+        for mode in [Repeat, Clamp, Mirror] {
+            assert_eq!(wrap(-3, 1, mode), 0);
+            assert_eq!(wrap(5, 1, mode), 0);
+        }
+    }
+
+    #[test]
+    fn bilinear_blends_neighbors() {
+        let texture =
+            Texture::new(2, 1, vec![color::BLACK, color::WHITE]).with_wrap(WrapMode::Clamp);
+        let sample = |texture: &Texture, u: f32| texture.sample(&Vec2::new(u, 0.5)).r;
+
+        assert_eq!(sample(&texture, 0.3), 0);
+        assert_eq!(sample(&texture, 0.6), 255);
+
+        let texture = texture.with_filter(FilterMode::Bilinear);
+        // Pixel centers sit at 0.25 and 0.75
+        assert_eq!(sample(&texture, 0.25), 0);
+        assert_eq!(sample(&texture, 0.5), 128);
+        assert_eq!(sample(&texture, 0.625), 191);
+        assert_eq!(sample(&texture, 0.75), 255);
+        // Clamping keeps the edges from blending with the far side
+        assert_eq!(sample(&texture, 0.0), 0);
+        assert_eq!(sample(&texture, 1.0), 255);
+    }
+}
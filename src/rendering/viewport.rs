@@ -1,6 +1,7 @@
 use crate::rendering::canvas::Canvas;
 use nalgebra_glm::{Mat4, Vec3, Vec4};
 
+#[derive(Copy, Clone)]
 pub struct Plane {
     normal: Vec4,
     d: f32,
@@ -19,9 +20,14 @@ impl Plane {
     }
 
     pub fn intersection(&self, p0: &Vec4, p1: &Vec4) -> Vec4 {
-        let t = (-self.d - self.normal.dot(p0)) / self.normal.dot(&(p1 - p0));
+        let t = self.intersection_factor(p0, p1);
         p0 + t * (p1 - p0)
     }
+
+    /// How far along the segment from p0 to p1 it crosses the plane (0 at p0, 1 at p1)
+    pub fn intersection_factor(&self, p0: &Vec4, p1: &Vec4) -> f32 {
+        (-self.d - self.normal.dot(p0)) / self.normal.dot(&(p1 - p0))
+    }
 }
 
 pub struct Viewport {
@@ -31,6 +37,7 @@ pub struct Viewport {
     vh: f32,
     d: f32,
     clipping_planes: [Plane; 5],
+    clip_space_planes: [Plane; 5],
     translation: Mat4,
     rotation: Mat4,
     transformation: Mat4,
@@ -45,32 +52,78 @@ impl Viewport {
             vh: height,
             d: depth,
             clipping_planes: [
-                Plane::new(&Vec4::new(0.0, 0.0, 1.0, 0.0), depth), // Near
+                Plane::new(&Vec4::new(0.0, 0.0, 1.0, 0.0), -depth), // Near
                 Plane::new(&Vec4::new(depth, 0.0, width / 2.0, 0.0), 0.0), // Left
-                Plane::new(&Vec4::new(depth, 0.0, -width / 2.0, 0.0), 0.0), // Right
-                Plane::new(&Vec4::new(0.0, depth, -height / 2.0, 0.0), 0.0), // Top
+                Plane::new(&Vec4::new(-depth, 0.0, width / 2.0, 0.0), 0.0), // Right
+                Plane::new(&Vec4::new(0.0, -depth, height / 2.0, 0.0), 0.0), // Top
                 Plane::new(&Vec4::new(0.0, depth, height / 2.0, 0.0), 0.0), // Bottom
             ],
+            clip_space_planes: [
+                Plane::new(&Vec4::new(0.0, 0.0, -1.0, 1.0), 0.0), // Near
+                Plane::new(&Vec4::new(1.0, 0.0, 0.0, 1.0), 0.0),  // Left
+                Plane::new(&Vec4::new(-1.0, 0.0, 0.0, 1.0), 0.0), // Right
+                Plane::new(&Vec4::new(0.0, -1.0, 0.0, 1.0), 0.0), // Top
+                Plane::new(&Vec4::new(0.0, 1.0, 0.0, 1.0), 0.0),  // Bottom
+            ],
             translation: Mat4::identity(),
             rotation: Mat4::identity(),
             transformation: Mat4::identity(),
         }
     }
 
+    /// Planes bounding the visible volume in camera space. Their normals
+    /// point inwards.
+    pub fn clipping_planes(&self) -> &[Plane] {
+        &self.clipping_planes
+    }
+
+    /// The same planes in clip space, in the same order. Points inside have
+    /// `-w <= x <= w`, `-w <= y <= w` and `z <= w`.
+    pub fn clip_space_planes(&self) -> &[Plane] {
+        &self.clip_space_planes
+    }
+
+    /// From camera to clip space.
+    ///
+    /// x and y are scaled so the edges of the viewport land on `±w`, w takes
+    /// the depth of the point and z the depth of the viewport, putting the
+    /// near plane at `z = w`.
+    pub fn get_projection(&self) -> Mat4 {
+        let (sx, sy) = (2.0 * self.d / self.vw, 2.0 * self.d / self.vh);
+        Mat4::new(
+            sx, 0.0, 0.0, 0.0, //
+            0.0, sy, 0.0, 0.0, //
+            0.0, 0.0, 0.0, self.d, //
+            0.0, 0.0, 1.0, 0.0,
+        )
+    }
+
+    /// Scales viewport x and y to canvas pixels. z is passed through untouched.
     pub fn viewport_to_canvas(&self, point: &Vec3) -> Vec3 {
         Vec3::new(
             point.x * (self.cw / self.vw),
             point.y * (self.ch / self.vh),
-            1.0,
+            point.z,
+        )
+    }
+
+    /// Divides a clip space position by w and maps it onto the canvas.
+    ///
+    /// The resulting z holds 1/w for depth buffering and perspective-correct
+    /// interpolation, which is 1/z in camera space.
+    pub fn clip_to_canvas(&self, position: &Vec4) -> Vec3 {
+        Vec3::new(
+            position.x / position.w * (self.cw / 2.0),
+            position.y / position.w * (self.ch / 2.0),
+            1.0 / position.w,
         )
     }
 
+    /// Projects a camera space vertex onto the canvas.
+    ///
+    /// The resulting z holds 1/z of the vertex for depth buffering.
     pub fn project_vertex(&self, vertex: &Vec4) -> Vec3 {
-        self.viewport_to_canvas(&Vec3::new(
-            vertex.x * self.d / vertex.z,
-            vertex.y * self.d / vertex.z,
-            1.0,
-        ))
+        self.clip_to_canvas(&(self.get_projection() * vertex))
     }
 
     pub fn get_translation(&self) -> &Mat4 {
@@ -0,0 +1,16 @@
+//! Helpers shared by the line based file formats.
+
+/// Parses the first `N` words as numbers
+pub(crate) fn numbers<const N: usize>(words: &[&str]) -> Result<[f32; N], String> {
+    if words.len() < N {
+        return Err(format!("expected {N} numbers, found {}", words.len()));
+    }
+    let mut values = [0.0; N];
+    for (value, word) in values.iter_mut().zip(words) {
+        *value = word
+            .parse()
+            .map_err(|_| format!("expected a number, found {word}"))?;
+    }
+
+    Ok(values)
+}
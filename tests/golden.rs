@@ -0,0 +1,211 @@
+//! Renders reference scenes offscreen and compares them against the images
+//! checked in under `tests/golden`.
+//!
+//! Run with `UPDATE_GOLDEN=1` to rewrite the references after an intended
+//! change to the rasterizer. When a comparison fails, the rendered image and
+//! a diff highlighting the pixels that moved are written to
+//! `target/golden-diff`.
+
+use nalgebra_glm::{Vec3, Vec4};
+use rasterizer::color;
+use rasterizer::color::Color;
+use rasterizer::image;
+use rasterizer::image::Image;
+use rasterizer::models::model::{default_cube, Instance};
+use rasterizer::models::shapes;
+use rasterizer::rendering::canvas::Canvas;
+use rasterizer::rendering::light::Light;
+use rasterizer::rendering::renderer::{RenderMode, Renderer};
+use rasterizer::rendering::scene::Scene;
+use rasterizer::rendering::texture::{FilterMode, Texture};
+use rasterizer::rendering::viewport::Viewport;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+const WIDTH: i32 = 160;
+const HEIGHT: i32 = 90;
+
+/// Largest difference allowed in any channel of a pixel
+const TOLERANCE: u8 = 2;
+
+/// Cubes given as (rotation, translation), lit like the interactive demo
+fn cube_scene(cubes: &[(Vec4, Vec4)]) -> Scene {
+    let cube = Rc::new(default_cube());
+    let mut scene = Scene::new();
+    for (rotation, translation) in cubes {
+        scene.add_instance(Rc::new(RefCell::new(Instance::new(
+            Rc::clone(&cube),
+            &Vec4::new(1.0, 1.0, 1.0, 0.0),
+            rotation,
+            translation,
+        ))));
+    }
+    scene.add_light(Rc::new(RefCell::new(Light::ambient(0.2, color::WHITE))));
+    scene.add_light(Rc::new(RefCell::new(Light::point(
+        &Vec3::new(2.0, 1.0, 0.0),
+        0.6,
+        color::WHITE,
+    ))));
+    scene.add_light(Rc::new(RefCell::new(Light::directional(
+        &Vec3::new(1.0, 4.0, 4.0),
+        0.2,
+        color::WHITE,
+    ))));
+
+    scene
+}
+
+fn render(scene: &Scene, mode: RenderMode) -> Image {
+    let canvas = Canvas::headless(WIDTH, HEIGHT);
+    let viewport = Viewport::new(12.80, 7.20, 6.0, &canvas);
+    let mut renderer = Renderer::new(canvas, viewport);
+    renderer.render_mode = mode;
+    renderer.canvas.clear(color::BLACK);
+    renderer.render_scene(scene);
+
+    renderer.canvas.to_image()
+}
+
+/// Compares an image against the reference called `name`, panicking with
+/// the number of pixels that moved if they differ
+fn assert_golden(name: &str, actual: &Image) {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let reference = root.join("tests/golden").join(format!("{name}.png"));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        actual.save(&reference).unwrap();
+        return;
+    }
+
+    let expected = image::load(&reference).unwrap_or_else(|error| {
+        panic!(
+            "cannot read {}: {error}. Run with UPDATE_GOLDEN=1 to create it.",
+            reference.display()
+        )
+    });
+    assert_eq!(
+        (expected.width, expected.height),
+        (actual.width, actual.height),
+        "{name} changed size"
+    );
+
+    let within_tolerance = |a: Color, b: Color| {
+        a.r.abs_diff(b.r) <= TOLERANCE
+            && a.g.abs_diff(b.g) <= TOLERANCE
+            && a.b.abs_diff(b.b) <= TOLERANCE
+    };
+    // Moved pixels in red over a dimmed copy of the reference
+    let diff: Vec<Color> = expected
+        .pixels
+        .iter()
+        .zip(&actual.pixels)
+        .map(|(&e, &a)| match within_tolerance(e, a) {
+            true => Color::new(e.r / 4, e.g / 4, e.b / 4),
+            false => color::RED,
+        })
+        .collect();
+    let moved = diff.iter().filter(|&&pixel| pixel == color::RED).count();
+    if moved == 0 {
+        return;
+    }
+
+    let output = root.join("target/golden-diff");
+    std::fs::create_dir_all(&output).unwrap();
+    actual
+        .save(output.join(format!("{name}.actual.png")))
+        .unwrap();
+    Image {
+        width: expected.width,
+        height: expected.height,
+        pixels: diff,
+    }
+    .save(output.join(format!("{name}.diff.png")))
+    .unwrap();
+    panic!(
+        "{moved} pixels of {name} differ from the reference, see {}",
+        output.display()
+    );
+}
+
+#[test]
+fn cube_rotations() {
+    let rotations = [
+        ("cube_front", Vec4::new(0.0, 0.0, 0.0, 0.0)),
+        ("cube_turned", Vec4::new(0.0, 0.6, 0.0, 0.0)),
+        ("cube_tilted", Vec4::new(0.5, 0.8, 0.0, 0.0)),
+        ("cube_rolled", Vec4::new(0.3, 0.4, 0.9, 0.0)),
+    ];
+    for (name, rotation) in rotations {
+        let scene = cube_scene(&[(rotation, Vec4::new(0.0, 0.0, 10.0, 0.0))]);
+        assert_golden(name, &render(&scene, RenderMode::Shaded));
+    }
+}
+
+#[test]
+fn clipped_geometry() {
+    let scene = cube_scene(&[
+        // Straddles the near plane
+        (Vec4::new(0.2, 0.5, 0.0, 0.0), Vec4::new(0.0, 0.0, 6.5, 0.0)),
+        // Sticks out of the left and bottom edges
+        (
+            Vec4::new(0.0, 0.3, 0.0, 0.0),
+            Vec4::new(-10.5, -6.0, 10.0, 0.0),
+        ),
+        // Entirely behind the camera
+        (
+            Vec4::new(0.0, 0.0, 0.0, 0.0),
+            Vec4::new(0.0, 0.0, -10.0, 0.0),
+        ),
+    ]);
+    assert_golden("clipped", &render(&scene, RenderMode::Filled));
+}
+
+#[test]
+fn render_modes() {
+    let scene = cube_scene(&[
+        (
+            Vec4::new(0.4, 0.6, 0.0, 0.0),
+            Vec4::new(-1.5, 0.0, 9.0, 0.0),
+        ),
+        (
+            Vec4::new(0.0, -0.3, 0.2, 0.0),
+            Vec4::new(1.8, 0.5, 12.0, 0.0),
+        ),
+    ]);
+    let modes = [
+        ("mode_wireframe", RenderMode::Wireframe),
+        ("mode_filled", RenderMode::Filled),
+        ("mode_shaded", RenderMode::Shaded),
+        ("mode_phong", RenderMode::Phong),
+        ("mode_filled_wireframe", RenderMode::FilledWireframe),
+    ];
+    for (name, mode) in modes {
+        assert_golden(name, &render(&scene, mode));
+    }
+}
+
+#[test]
+fn textured_floor() {
+    // A checkered floor running off towards the horizon, where mip mapping
+    // keeps the squares from shimmering
+    let texture = Rc::new(
+        Texture::checkerboard(256, 16, color::WHITE, color::BLUE)
+            .with_mipmaps()
+            .with_filter(FilterMode::Trilinear),
+    );
+    let mut floor = shapes::plane(40.0, 60.0, 1, 1);
+    floor.triangles = floor
+        .triangles
+        .into_iter()
+        .map(|triangle| triangle.with_texture(Rc::clone(&texture)))
+        .collect();
+
+    let mut scene = Scene::new();
+    scene.add_instance(Rc::new(RefCell::new(Instance::new(
+        Rc::new(floor),
+        &Vec4::new(1.0, 1.0, 1.0, 0.0),
+        &Vec4::new(0.0, 0.3, 0.0, 0.0),
+        &Vec4::new(0.0, -3.0, 35.0, 0.0),
+    ))));
+    assert_golden("textured_floor", &render(&scene, RenderMode::Filled));
+}